@@ -5,11 +5,10 @@ Simple template-based parsing and transforming of a text file with values.
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")] // hide console window on Windows in release
 
 // use std::thread;
-use std::fs::File;
-use std::io::{
-    BufReader,
-    BufRead, 
-    Write
+use std::sync::Arc;
+use std::time::{
+    Duration,
+    Instant
 };
 
 use eframe::egui;
@@ -21,15 +20,40 @@ use eframe:: {
 mod widgets;
 use crate::widgets::errorfield::ErrorField;
 use crate::widgets::switch::Switch;
+use crate::widgets::picker::Picker;
+use crate::widgets::highlight;
+use crate::widgets::savedialog::{
+    Choice,
+    SaveDialog
+};
 
 mod models;
 use crate::models::table::Table;
 use crate::models::parser::Parser;
 use crate::models::parser::Origin;
+use crate::models::inference;
+use crate::models::dialect::Dialect;
+use crate::models::encoding::{
+    Encoding,
+    LineEnding
+};
+use crate::models::profile::Profile;
+use crate::models::loader;
+use crate::models::loader::Loaded;
+use crate::models::worker::Worker;
+use crate::models::exporter;
+use crate::models::exporter::Exported;
+use crate::models::placeholder;
+use crate::models::format;
+use crate::models::format::{
+    Format,
+    SqlDialect
+};
 
 
 const WINDOW_SIZE:  egui::Vec2 = egui::Vec2::new(640.0, 480.0);
 const ACCENT_COLOR: egui::Color32 = egui::Color32::from_rgb(0, 153, 127); // HSL(170,100,30)
+const LOAD_DELAY:   Duration = Duration::from_millis(400); // Wait for more key presses before reloading file.
 
 #[derive(serde::Deserialize, serde::Serialize, PartialEq, Copy, Clone)]
 enum InterfaceMode
@@ -41,7 +65,6 @@ enum InterfaceMode
 #[derive(Default, PartialEq)]
 enum StateTracker 
 {
-    Saving,   // When saving is in progress.
     Dragging, // When export button is being dragged
     #[default] Idle
 }
@@ -62,23 +85,67 @@ impl <T,E> MessageOnly for Result<T,E> where E: ToString {
 
 #[allow(clippy::struct_excessive_bools)]
 #[derive(serde::Deserialize, serde::Serialize)]
+#[serde(default)] // Settings missing in an older app.ron are taken from Default.
 struct Reshaper
 {
     source: String,
     target: String,
+    comment: String,
+    name: String,        // Name of templates, used in file name of export.
     ui_size: f32,
     ui_mode: InterfaceMode,
-    do_skip_1: bool,
+    profile: Profile,
+    #[serde(skip_serializing)] do_skip_1: bool, // "Skip first row" of older app.ron, read into profile.
     do_quotes: bool,
     do_header: bool,
+    do_names: bool,
+    do_reject: bool,
+    do_bom: bool,
+    do_terminate: bool,  // Exported text ends with a line break.
+    in_encoding: Encoding,
+    out_encoding: Encoding,
+    out_ending: LineEnding,
+    out_folder: String,  // Empty for desktop (or home folder if there is no desktop).
+    out_pattern: String, // File name of export, with placeholders.
+    out_format: Format,
+    out_options: format::Options, // Settings of formats, like SQL table name.
+    do_anchor: bool,
+    do_nocase: bool,
+    do_unicode: bool,
 
     #[serde(skip)] parser: Parser,
-    #[serde(skip)] data: Table,
+    #[serde(skip)] commenter: Parser, // Reads comment lines with comment template.
+    #[serde(skip)] data: Arc<Table>, // Shared with exporter while written.
+    #[serde(skip)] notes: Table, // Comment lines read with comment template.
     #[serde(skip)] path: String,
     #[serde(skip)] source_error: String,
     #[serde(skip)] target_error: String,
+    #[serde(skip)] profile_error: String,
+    #[serde(skip)] comment_error: String,
+    #[serde(skip)] load_error: String,
+    #[serde(skip)] partial: usize,
+    #[serde(skip)] proposal: Option<String>,
+    #[serde(skip)] header: Option<String>, // Source template made from names in first row, offered as suggestion.
+    #[serde(skip)] dialect: Option<Dialect>,
+    #[serde(skip)] encoding: Encoding, // Used when reading file (detected if in_encoding is Auto).
+    #[serde(skip)] ending: LineEnding, // Detected when reading file.
+    #[serde(skip)] loader: Option<Worker<Loaded>>, // Reads file in the background.
+    #[serde(skip)] load_at: Option<Instant>,       // When to reload file after template changes.
+    #[serde(skip)] exporter: Option<Worker<Exported>>,
+    #[serde(skip)] dialog: Option<SaveDialog>,
+    #[serde(skip)] conflict: Option<std::path::PathBuf>, // Path to export to, that is already taken.
+    #[serde(skip)] notice: Option<Result<String, String>>, // Outcome of last export.
+    #[serde(skip)] stale: bool,                    // Table is from before reload, and is replaced when new rows arrive.
     #[serde(skip)] state: StateTracker,
-    #[serde(skip)] target_view: bool
+    #[serde(skip)] target_view: bool,
+    #[serde(skip)] lines_view: bool,
+    #[serde(skip)] notes_view: bool,
+    #[serde(skip)] only_failures: bool,
+    #[serde(skip)] profiling: bool, // Rules for skipping lines are shown.
+    #[serde(skip)] picking: bool,  // Source template is built by selecting parts of a row.
+    #[serde(skip)] pick_row: usize,
+    #[serde(skip)] picks: Vec<(usize, usize, String)>,
+    #[serde(skip)] pick_line: String // Line that picks were made in, they do not fit others (like the same line read in another way).
 }
 
 impl Default for Reshaper
@@ -87,18 +154,61 @@ impl Default for Reshaper
         Self {
             source: String::from("<date> <time>: <systolic>/<diastolic> <pulse>"),
             target: String::from("<date>,<pulse>,<systolic>,<diastolic>"),
+            comment: String::from("# Snitt <period> = <systolic>/<diastolic> <pulse>"),
+            name: String::from("blodtryck"),
             ui_size: 1.2,
             ui_mode: InterfaceMode::Dark,
-            do_skip_1: true,
+            profile: Profile::default(),
+            do_skip_1: false,
             do_quotes: false,
             do_header: false,
+            do_names: false,
+            do_reject: false,
+            do_bom: false,
+            do_terminate: true,
+            in_encoding: Encoding::Auto,
+            out_encoding: Encoding::Utf8,
+            out_ending: LineEnding::Lf,
+            out_folder: String::new(),
+            out_pattern: String::from("{stem}.out.{ext}"),
+            out_format: Format::Text,
+            out_options: format::Options::default(),
+            do_anchor: false,
+            do_nocase: false,
+            do_unicode: false,
             parser: Parser::new(),
-            data: Table::new(),
+            commenter: Parser::new(),
+            data: Arc::new(Table::new()),
+            notes: Table::new(),
             path: String::new(),
             source_error: String::new(),
             target_error: String::new(),
+            profile_error: String::new(),
+            comment_error: String::new(),
+            load_error: String::new(),
+            partial: 0,
+            proposal: None,
+            header: None,
+            dialect: None,
+            encoding: Encoding::Auto,
+            ending: LineEnding::Lf,
+            loader: None,
+            load_at: None,
+            exporter: None,
+            dialog: None,
+            conflict: None,
+            notice: None,
+            stale: false,
             state: StateTracker::Idle,
-            target_view: true
+            target_view: true,
+            lines_view: false,
+            notes_view: false,
+            only_failures: false,
+            profiling: false,
+            picking: false,
+            pick_row: 0,
+            picks: Vec::new(),
+            pick_line: String::new()
         }
     }
 }
@@ -107,8 +217,18 @@ impl Reshaper
 {
     fn new (context: &eframe::CreationContext<'_>) -> Self {
         let mut object = if let Some(ps) = context.storage { eframe::get_value(ps, eframe::APP_KEY).unwrap_or_default() } else { Reshaper::default() };
+        if object.do_skip_1 {
+            object.profile.skip_head = 1;
+            object.do_skip_1 = false;
+        }
+        object.parser.set_strict(object.do_anchor).set_unicode(object.do_unicode);
+        object.commenter.set_strict(object.do_anchor).set_unicode(object.do_unicode);
+        let _ = object.parser.set_nocase(object.do_nocase); // Errors come from templates, and are found when they are set below.
+        let _ = object.commenter.set_nocase(object.do_nocase);
         object.source_error = object.parser.set_source(&object.source).as_message();
         object.target_error = object.parser.set_target(&object.target).as_message();
+        object.profile_error = object.profile.set_pattern(&object.profile.pattern.clone()).as_message();
+        object.set_comment();
         object.target_view = true; // Why is this set to false during deserialization?
         Self::set_fonts(&context.egui_ctx);
         Self::set_style(&context.egui_ctx, object.ui_mode);
@@ -191,12 +311,50 @@ impl Reshaper
                 self.source_error = self.parser.set_source(&self.source).as_message();
                 self.target_error = self.parser.set_target(&self.target).as_message(); // Source errors can cause target errors.
                 if  self.source_error.is_empty() && self.target_error.is_empty(){
-                    self.load_file();
+                    self.load_later();
                 }
             }
             if !self.source_error.is_empty() {
                 ui.label(egui::RichText::new(&self.source_error).color(egui::Color32::RED));
             }
+            if let Some(proposal) = self.proposal.clone() && proposal != self.source {
+                ui.horizontal(|ui| {
+                    ui.label(egui::RichText::new("Suggestion:").weak());
+                    ui.label(egui::RichText::new(&proposal).monospace());
+                    if ui.small_button("Use").on_hover_text("Replace source template with suggestion.").clicked() {
+                        self.source = proposal;
+                        self.source_error = self.parser.set_source(&self.source).as_message();
+                        self.target_error = self.parser.set_target(&self.target).as_message();
+                        self.load_file();
+                    }
+                });
+            }
+            ui.horizontal(|ui| {
+                if ui.checkbox(&mut self.do_anchor, "Match whole line").changed() {
+                    self.parser.set_strict(self.do_anchor);
+                    self.commenter.set_strict(self.do_anchor);
+                    self.load_file();
+                }
+                if ui.checkbox(&mut self.do_nocase, "Ignore case").changed() {
+                    let _ = self.parser.set_nocase(self.do_nocase);
+                    let _ = self.commenter.set_nocase(self.do_nocase);
+                    self.set_templates();
+                    self.load_file();
+                }
+                if !self.data.is_empty() {
+                    ui.toggle_value(&mut self.picking, "\u{e3c9} Pick from line").on_hover_text("Build source template by selecting parts of a line.");
+                }
+                ui.toggle_value(&mut self.profiling, "\u{e152} Skip lines").on_hover_text("Set which lines of file to skip, like comments and preambles.");
+                if ui.checkbox(&mut self.do_unicode, "Normalize Unicode").on_hover_text("Compose characters like \"a\" + \"\u{30a}\" into \"\u{e5}\" before matching.").changed() {
+                    self.parser.set_unicode(self.do_unicode);
+                    self.commenter.set_unicode(self.do_unicode);
+                    self.set_templates(); // Templates are normalized too.
+                    self.load_file();
+                }
+            });
+            if self.profiling {
+                self.create_profile(ui);
+            }
             ui.add_space(12.0);
             ui.label(egui::RichText::new("TARGET TEMPLATE").small().weak());
             if ui.add(ErrorField::new(&mut self.target, self.target_error.is_empty())).changed() {
@@ -208,33 +366,127 @@ impl Reshaper
         });
         if !self.data.is_empty() {
             ui.add_space(12.0);
-            ui.horizontal(|ui| {
-                if ui.add(Switch::new(self.target_view)).clicked() {
-                    self.target_view = !self.target_view;
-                }
-                ui.label(format!("{} data shown in table.", if self.target_view {"Transformed"} else {"Original"}));
-                ui.add_space(12.0);
-                let dragger = ui.small_button("\u{e074} Drag to export").interact(egui::Sense::click_and_drag()).highlight();
-                if  dragger.drag_started() {
-                    self.state = StateTracker::Dragging;
-                }
-                let outside = !ui.ctx().content_rect().contains(ui.input(|i| i.pointer.interact_pos()).unwrap_or_default());
-                if  dragger.drag_stopped() {
-                    ui.ctx().set_cursor_icon(egui::CursorIcon::Default);
-                    self.state = StateTracker::Idle;
-                    if outside {
-                        // thread::spawn(|| { // Should be this easy (nogo Rust).
-                            self.save_file();
-                        // });
+            self.create_options(ui);
+        }
+    }
+
+    // Rules for skipping lines, shown below source template. Data is reloaded when they change.
+    fn create_profile (&mut self, ui: &mut egui::Ui) {
+        let mut changed = false;
+        ui.horizontal(|ui| {
+            ui.label("Skip first");
+            changed |= ui.add(egui::DragValue::new(&mut self.profile.skip_head)).changed();
+            ui.label("and last");
+            changed |= ui.add(egui::DragValue::new(&mut self.profile.skip_tail)).changed();
+            ui.label("lines. Comments start with");
+            changed |= ui.add(egui::TextEdit::singleline(&mut self.profile.comments).desired_width(64.0)).on_hover_text("Prefixes separated by blanks, like \"# ; //\".").changed();
+            ui.label("Skip lines matching");
+            let mut pattern = self.profile.pattern.clone();
+            if ui.add(ErrorField::new(&mut pattern, self.profile_error.is_empty())).on_hover_text("Regular expression, like \"^Total\".").changed() {
+                self.profile_error = self.profile.set_pattern(&pattern).as_message();
+                changed = true;
+            }
+        });
+        if !self.profile_error.is_empty() {
+            ui.label(egui::RichText::new(&self.profile_error).color(egui::Color32::RED));
+        }
+        ui.horizontal(|ui| {
+            ui.label("Read comments with template");
+            if ui.add(ErrorField::new(&mut self.comment, self.comment_error.is_empty()))
+                .on_hover_text("Comments matching this template are shown in a table of their own, other comments are skipped.")
+                .changed() {
+                self.set_comment();
+                changed = true;
+            }
+        });
+        if !self.comment_error.is_empty() {
+            ui.label(egui::RichText::new(&self.comment_error).color(egui::Color32::RED));
+        }
+        if changed && !self.path.is_empty() {
+            self.load_later();
+        }
+    }
+
+    // Options and status for loaded data, shown below templates.
+    fn create_options (&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            if ui.add(Switch::new(self.target_view)).clicked() {
+                self.target_view = !self.target_view;
+            }
+            ui.label(format!("{} data shown in table.", if self.target_view {"Transformed"} else {"Original"}));
+            ui.toggle_value(&mut self.lines_view, "\u{e8ee} Lines").on_hover_text("Show original lines with the part found for each variable highlighted.");
+            if !self.notes.is_empty() {
+                ui.toggle_value(&mut self.notes_view, format!("\u{e0b9} Comments ({})", self.notes.row_count())).on_hover_text("Show comments read with comment template.");
+            }
+            ui.add_space(12.0);
+            let dragger = ui.small_button("\u{e074} Drag to export").interact(egui::Sense::click_and_drag()).highlight();
+            if  dragger.drag_started() {
+                self.state = StateTracker::Dragging;
+            }
+            let outside = !ui.ctx().content_rect().contains(ui.input(|i| i.pointer.interact_pos()).unwrap_or_default());
+            if  dragger.drag_stopped() {
+                ui.ctx().set_cursor_icon(egui::CursorIcon::Default);
+                self.state = StateTracker::Idle;
+                if outside {
+                    if self.loader.is_some() {
+                        self.notice = Some(Err(String::from("Not exported, since file is still being read. Try again when all rows are read.")));
+                    } else if self.exporter.is_some() {
+                        self.notice = Some(Err(String::from("Not exported, since another export is being written.")));
+                    } else {
+                        match self.out_folder() {
+                            Some(folder) => self.save_file(folder.join(self.out_name())),
+                            None => self.notice = Some(Err(String::from("Could not find a folder to write to.")))
+                        }
                     }
                 }
-                if self.state == StateTracker::Dragging {
-                    ui.ctx().set_cursor_icon(if outside { egui::CursorIcon::Grabbing } else { egui::CursorIcon::NoDrop });
-                }
-                ui.checkbox(&mut self.do_skip_1, "Skip first row");
-                ui.checkbox(&mut self.do_quotes, "Write quotation marks");
-                ui.checkbox(&mut self.do_header, "Write headers");
+            }
+            if self.state == StateTracker::Dragging {
+                ui.ctx().set_cursor_icon(if outside { egui::CursorIcon::Grabbing } else { egui::CursorIcon::NoDrop });
+            }
+            let idle = self.loader.is_none() && self.exporter.is_none();
+            if ui.add_enabled(idle, egui::Button::new("\u{e161} Export ...").small()).on_hover_text("Choose folder and name of file to export to.").clicked() {
+                self.dialog = Some(SaveDialog::new(&self.out_folder().unwrap_or_default(), &self.out_name()));
+            }
+            if ui.checkbox(&mut self.do_names, "First row is header").on_hover_text("Skip first row, and suggest a source template made from its names.").changed() {
+                self.load_file();
+            }
+            let text = self.out_format == Format::Text; // Other formats have their own quoting and headers.
+            ui.add_enabled(text, egui::Checkbox::new(&mut self.do_quotes, "Write quotation marks"));
+            ui.add_enabled(text, egui::Checkbox::new(&mut self.do_header, "Write headers"));
+            ui.add_enabled(self.out_format != Format::Xlsx, egui::Checkbox::new(&mut self.do_terminate, "End last row"))
+                .on_hover_text("End last row (or epilogue) with a line break.");
+            ui.checkbox(&mut self.do_reject, "Write rejects").on_hover_text("Write lines that do not match source template to a separate file.");
+        });
+        ui.horizontal(|ui| {
+            ui.label(egui::RichText::new(format!("Read as {} with {} line endings.", self.encoding.name(), self.ending.name())).weak());
+            if let Some(dialect) = &self.dialect {
+                ui.label(egui::RichText::new(dialect.describe()).weak());
+            }
+        });
+        if self.partial > 0 {
+            ui.label(egui::RichText::new(format!("{} lines only partially match source template.", self.partial)).color(egui::Color32::RED));
+        }
+        let failures = self.data.failures().len();
+        if failures > 0 {
+            ui.horizontal(|ui| {
+                ui.label(egui::RichText::new(format!("{failures} of {} lines do not match source template.", self.data.row_count())).color(egui::Color32::RED));
+                ui.checkbox(&mut self.only_failures, "Show only these");
+            });
+        } else {
+            self.only_failures = false;
+        }
+        if let Some(notice) = &self.notice {
+            let mut close = false;
+            ui.horizontal(|ui| {
+                match notice {
+                    Ok(message) => ui.label(egui::RichText::new(message).color(ACCENT_COLOR)),
+                    Err(message) => ui.label(egui::RichText::new(message).color(egui::Color32::RED))
+                };
+                close = ui.small_button("\u{e5cd}").on_hover_text("Hide message.").clicked();
             });
+            if close {
+                self.notice = None;
+            }
         }
     }
 
@@ -247,6 +499,43 @@ impl Reshaper
                 }
             });
             ui.add_space(24.0);
+            ui.vertical(|ui| {
+                ui.label(egui::RichText::new("INPUT ENCODING").small().weak());
+                let before = self.in_encoding;
+                egui::ComboBox::from_id_salt("Input encoding").selected_text(self.in_encoding.name()).show_ui(ui, |ui| {
+                    for encoding in Encoding::INPUT {
+                        ui.selectable_value(&mut self.in_encoding, encoding, encoding.name());
+                    }
+                });
+                if before != self.in_encoding && !self.path.is_empty() {
+                    self.load_file();
+                }
+            });
+            ui.add_space(24.0);
+            ui.vertical(|ui| {
+                ui.label(egui::RichText::new("OUTPUT ENCODING").small().weak());
+                ui.add_enabled_ui(self.out_format != Format::Xlsx, |ui| ui.horizontal(|ui| { // Workbooks are always UTF-8 inside.
+                    egui::ComboBox::from_id_salt("Output encoding").selected_text(self.out_encoding.name()).show_ui(ui, |ui| {
+                        for encoding in Encoding::OUTPUT {
+                            ui.selectable_value(&mut self.out_encoding, encoding, encoding.name());
+                        }
+                    });
+                    ui.add_enabled(!self.out_encoding.bom().is_empty(), egui::Checkbox::new(&mut self.do_bom, "BOM"))
+                        .on_hover_text("Start file with a byte order mark.");
+                }));
+            });
+            ui.add_space(24.0);
+            ui.vertical(|ui| {
+                ui.label(egui::RichText::new("LINE ENDINGS").small().weak());
+                ui.add_enabled_ui(self.out_format != Format::Xlsx, |ui| {
+                    egui::ComboBox::from_id_salt("Line endings").selected_text(self.out_ending.name()).show_ui(ui, |ui| {
+                        for ending in LineEnding::ALL {
+                            ui.selectable_value(&mut self.out_ending, ending, ending.name());
+                        }
+                    }).response.on_hover_text("LF for Linux and macOS, CRLF for Windows.");
+                });
+            });
+            ui.add_space(24.0);
             ui.vertical(|ui| {
                 ui.label(egui::RichText::new("DARK MODE").small().weak());
                 if ui.add(Switch::new(InterfaceMode::Dark == self.ui_mode)).clicked() {
@@ -263,6 +552,119 @@ impl Reshaper
                 }
             });
         });
+        ui.add_space(12.0);
+        ui.horizontal(|ui| {
+            ui.vertical(|ui| {
+                ui.label(egui::RichText::new("EXPORT FOLDER").small().weak());
+                let hint = self.out_folder().map(|f| f.display().to_string()).unwrap_or_default();
+                ui.add(egui::TextEdit::singleline(&mut self.out_folder).hint_text(hint).desired_width(200.0))
+                    .on_hover_text("Folder that \"Drag to export\" writes to, and where \"Export\" starts. Empty for desktop.");
+            });
+            ui.add_space(24.0);
+            ui.vertical(|ui| {
+                ui.label(egui::RichText::new("FILE NAME").small().weak());
+                ui.add(egui::TextEdit::singleline(&mut self.out_pattern).desired_width(160.0))
+                    .on_hover_text("Placeholders: {stem} (of file read), {template} (name below), {date}, {time} and {ext} (of format).");
+            });
+            ui.add_space(24.0);
+            ui.vertical(|ui| {
+                ui.label(egui::RichText::new("TEMPLATE NAME").small().weak());
+                ui.add(egui::TextEdit::singleline(&mut self.name).desired_width(120.0))
+                    .on_hover_text("Name of templates, used for {template} in file name.");
+            });
+            ui.add_space(24.0);
+            ui.vertical(|ui| {
+                ui.label(egui::RichText::new("FORMAT").small().weak());
+                egui::ComboBox::from_id_salt("Format").selected_text(self.out_format.name()).show_ui(ui, |ui| {
+                    for format in Format::ALL {
+                        ui.selectable_value(&mut self.out_format, format, format.name());
+                    }
+                }).response.on_hover_text("Other formats than target template use target variable names, and write numbers as numbers.");
+            });
+        });
+        if matches!(self.out_format, Format::Text | Format::Sql | Format::Xml) {
+            ui.add_space(12.0);
+            self.create_format(ui);
+        }
+    }
+
+    // Settings for the chosen format, shown below export settings.
+    fn create_format (&mut self, ui: &mut egui::Ui) {
+        let options = &mut self.out_options;
+        if self.out_format == Format::Text {
+            let placeholders = "Placeholders: {rows} (number of rows), {file} (name of source file), {date} and {time}.";
+            ui.horizontal(|ui| {
+                ui.vertical(|ui| {
+                    ui.label(egui::RichText::new("PROLOGUE").small().weak());
+                    ui.add(egui::TextEdit::multiline(&mut options.prologue).hint_text("[").desired_rows(2).desired_width(200.0))
+                        .on_hover_text(format!("Text written before rows (and headers). {placeholders}"));
+                });
+                ui.add_space(24.0);
+                ui.vertical(|ui| {
+                    ui.label(egui::RichText::new("EPILOGUE").small().weak());
+                    ui.add(egui::TextEdit::multiline(&mut options.epilogue).hint_text("]").desired_rows(2).desired_width(200.0))
+                        .on_hover_text(format!("Text written after rows. {placeholders}"));
+                });
+                ui.add_space(24.0);
+                ui.vertical(|ui| {
+                    ui.label(egui::RichText::new("ROW SEPARATOR").small().weak());
+                    ui.add(egui::TextEdit::singleline(&mut options.separator).hint_text("\\n").desired_width(80.0))
+                        .on_hover_text("Written between rows, like \",\\n\". Line break is written as \\n and tab as \\t.");
+                });
+            });
+            return;
+        }
+        if self.out_format == Format::Xml {
+            ui.horizontal(|ui| {
+                ui.vertical(|ui| {
+                    ui.label(egui::RichText::new("ROOT ELEMENT").small().weak());
+                    ui.add(egui::TextEdit::singleline(&mut options.root).hint_text("rows").desired_width(120.0));
+                });
+                ui.add_space(24.0);
+                ui.vertical(|ui| {
+                    ui.label(egui::RichText::new("ROW ELEMENT").small().weak());
+                    ui.add(egui::TextEdit::singleline(&mut options.row).hint_text("row").desired_width(120.0));
+                });
+                ui.add_space(24.0);
+                ui.vertical(|ui| {
+                    ui.label(egui::RichText::new("ATTRIBUTES").small().weak());
+                    ui.horizontal_wrapped(|ui| {
+                        for name in self.parser.variables(Origin::Target) {
+                            let mut attribute = options.attributes.contains(name);
+                            if ui.checkbox(&mut attribute, name).on_hover_text("Write as attribute of row, instead of as child element.").changed() {
+                                options.attributes.retain(|a| a != name);
+                                if attribute {
+                                    options.attributes.push(name.clone());
+                                }
+                            }
+                        }
+                    });
+                });
+            });
+            return;
+        }
+        ui.horizontal(|ui| {
+            ui.vertical(|ui| {
+                ui.label(egui::RichText::new("TABLE NAME").small().weak());
+                ui.add(egui::TextEdit::singleline(&mut options.table).hint_text(&self.name).desired_width(120.0))
+                    .on_hover_text("Empty for template name.");
+            });
+            ui.add_space(24.0);
+            ui.vertical(|ui| {
+                ui.label(egui::RichText::new("SQL DIALECT").small().weak());
+                egui::ComboBox::from_id_salt("SQL dialect").selected_text(options.sql.name()).show_ui(ui, |ui| {
+                    for dialect in SqlDialect::ALL {
+                        ui.selectable_value(&mut options.sql, dialect, dialect.name());
+                    }
+                });
+            });
+            ui.add_space(24.0);
+            ui.vertical(|ui| {
+                ui.label(egui::RichText::new("COLUMN TYPES").small().weak());
+                ui.add(egui::TextEdit::singleline(&mut options.types).hint_text("pulse SMALLINT, date DATE").desired_width(240.0))
+                    .on_hover_text("Types of columns, separated by commas. Types of other columns are found from their values.");
+            });
+        });
     }
 
     fn create_table (&self, ui: &mut egui::Ui) {
@@ -284,8 +686,8 @@ impl Reshaper
                 });
             })
             .body(|body| {
-                body.rows(20.0, self.data.row_count(), |mut row| {
-                    let observation = row.index();
+                body.rows(20.0, self.shown_count(), |mut row| {
+                    let observation = self.shown_index(row.index());
                     for variable in self.parser.positions(origin) {
                         row.col(|ui| {
                             if let Some(text) = self.data.get(observation, *variable) {
@@ -293,66 +695,349 @@ impl Reshaper
                             }
                         });
                     };
+                    if self.data.get_error(observation).is_some() {
+                        row.response().on_hover_ui(|ui| self.explain(ui, observation));
+                    }
                 });
             });
         }
     }
 
-    fn load_file (&mut self) {
-        self.data = Table::new();
-        if let Ok(file) = File::open(&self.path) {
-            let reader  = BufReader::new(file);
-            let lines = reader.lines();
-            let skips = usize::from(self.do_skip_1); // Bug in clippy won't allow let if with boolean?
-            for row in lines.skip(skips) {
-                let Ok(row) = row else { continue };
-                if !row.is_empty() && !row.starts_with('#') { // Treat these lines as comments.
-                    self.data.add(row.as_str(), self.parser.split(row.as_str()).unwrap_or_default());
+    // Comments read with comment template, in a table with a column for each variable.
+    fn create_notes (&self, ui: &mut egui::Ui) {
+        let count = self.commenter.variables(Origin::Source).count();
+        if count == 0 {
+            return;
+        }
+        ui.style_mut().spacing.item_spacing = egui::Vec2::new(16.0, 0.0);
+        egui_extras::TableBuilder::new(ui)
+            .cell_layout(egui::Layout::left_to_right(egui::Align::Center))
+            .column(egui_extras::Column::auto())
+            .columns(egui_extras::Column::auto().resizable(true), count)
+            .header(24.0, |mut header| {
+                header.col(|ui| {
+                    ui.strong("Line");
+                });
+                self.commenter.variables(Origin::Source).for_each(|v| {
+                    header.col(|ui| {
+                        ui.strong(v);
+                    });
+                });
+            })
+            .body(|body| {
+                body.rows(20.0, self.notes.row_count(), |mut row| {
+                    let index = row.index();
+                    row.col(|ui| {
+                        ui.label(egui::RichText::new(self.notes.get_line(index).unwrap_or_default().to_string()).weak());
+                    });
+                    for variable in self.commenter.positions(Origin::Source) {
+                        row.col(|ui| {
+                            if let Some(text) = self.notes.get(index, *variable) {
+                                ui.label(text);
+                            }
+                        });
+                    }
+                });
+            });
+    }
+
+    // Number of rows shown in table (all or only failures).
+    fn shown_count (&self) -> usize {
+        if self.only_failures { self.data.failures().len() } else { self.data.row_count() }
+    }
+
+    // Index into table of the row shown at a position.
+    fn shown_index (&self, position: usize) -> usize {
+        if self.only_failures { self.data.failures().get(position).copied().unwrap_or_default() } else { position }
+    }
+
+    // Tooltip for rows that does not match source template.
+    fn explain (&self, ui: &mut egui::Ui, index: usize) {
+        let text = self.data.get_text(index).unwrap_or_default();
+        ui.strong(format!("Line {}", self.data.get_line(index).unwrap_or_default()));
+        match self.parser.explain(text) {
+            Some(mismatch) => {
+                ui.label(egui::RichText::new(text).monospace());
+                ui.label(egui::RichText::new(format!("{}^", " ".repeat(mismatch.column - 1))).monospace().color(egui::Color32::RED));
+                ui.label(mismatch.reason);
+                if !mismatch.matched.is_empty() {
+                    ui.label(egui::RichText::new(format!("Matched: {}", mismatch.matched)).weak());
                 }
-            };
+            },
+            None => { 
+                ui.label(self.data.get_error(index).unwrap_or_default());
+            }
         }
     }
 
-    fn save_file (&mut self) {
-        self.state = StateTracker::Saving;
-        if let Some(desktop) = dirs::desktop_dir() {
-            let original = std::path::PathBuf::from(&self.path);
-            let mut path = desktop.join(original.file_name().unwrap_or_default());
-            path.set_extension("out.csv");
-            if let Ok(mut file) = File::create(path) {
-                if self.do_header {
-                    let mut target = String::new();
-                    for header in self.parser.variables(Origin::Target) {
-                        if self.do_quotes { 
-                            target.push('"');
-                        }
-                        target.push_str(header);
-                        if self.do_quotes { 
-                            target.push('"');
-                        }
-                        target.push(',');
-                    }
-                    if  target.ends_with(',') {
-                        target.pop();
-                    }
-                    target.push('\n');
-                    if file.write_all(target.as_bytes()).is_err() {
-                        self.state = StateTracker::Idle;
-                        return; // Result should be returned to inform user.
-                    }
+    fn create_lines (&self, ui: &mut egui::Ui) {
+        let height = ui.text_style_height(&egui::TextStyle::Monospace) + 4.0;
+        ui.style_mut().spacing.item_spacing = egui::Vec2::new(16.0, 4.0);
+        egui::ScrollArea::both().auto_shrink(false).show_rows(ui, height, self.shown_count(), |ui, rows| {
+            for position in rows {
+                let index = self.shown_index(position);
+                let text = self.data.get_text(index).unwrap_or_default();
+                let slices = self.data.get_slices(index).map(Vec::as_slice).unwrap_or_default();
+                let failed = self.data.get_error(index).is_some();
+                let color = if failed { egui::Color32::RED } else { ui.visuals().text_color() };
+                let label = ui.add(egui::Label::new(highlight::layout(ui, text, slices.iter(), color)).extend());
+                if failed {
+                    label.on_hover_ui(|ui| self.explain(ui, index));
                 }
-                for row in 0..self.data.row_count() {
-                    if let Some(parts) = self.data.get_parts(row) && let Ok(mut target) = self.parser.transform(parts, self.do_quotes) {
-                        target.push('\n');
-                        if file.write_all(target.as_bytes()).is_err() {
-                            self.state = StateTracker::Idle;
-                            return;
+            }
+        });
+    }
+
+    fn create_picker (&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Select parts of line");
+            let mut number = self.pick_row + 1;
+            if ui.add(egui::DragValue::new(&mut number).range(1..=self.data.row_count().max(1))).changed() {
+                self.pick_row = number - 1;
+                self.picks.clear();
+            }
+            ui.label("with the mouse to make them variables. Click on a part to remove it.");
+        });
+        ui.add_space(12.0);
+        let line = self.data.get_text(self.pick_row).unwrap_or_default().to_string();
+        if line != self.pick_line {
+            self.picks.clear();
+            self.pick_line.clone_from(&line);
+        }
+        let mut changed = ui.add(Picker::new(&line, &mut self.picks)).changed();
+        ui.add_space(12.0);
+        egui::Grid::new("Picks").num_columns(2).spacing(egui::Vec2::new(16.0, 8.0)).show(ui, |ui| {
+            for (index, (start, end, name)) in self.picks.iter_mut().enumerate() {
+                ui.label(egui::RichText::new(line.get(*start..*end).unwrap_or_default()).monospace().background_color(highlight::color(index)));
+                changed |= ui.add(ErrorField::new(name, !name.trim().is_empty())).changed();
+                ui.end_row();
+            }
+        });
+        if changed && !self.picks.is_empty() {
+            self.source = Parser::from_picks(&line, &self.picks);
+            self.source_error = self.parser.set_source(&self.source).as_message();
+            self.target_error = self.parser.set_target(&self.target).as_message();
+            if self.source_error.is_empty() {
+                self.load_later();
+            }
+        }
+    }
+
+    // Starts reading file in the background, rows are added to table as they are read (in receive_rows).
+    fn load_file (&mut self) {
+        if let Some(loader) = self.loader.take() {
+            loader.cancel();
+        }
+        self.load_at = None;
+        self.stale = true; // Table is kept until new rows arrive, so that it does not blink when reloaded.
+        self.load_error.clear();
+        if self.path.is_empty() {
+            return;
+        }
+        self.loader = Some(loader::start(loader::Request {
+            path: self.path.clone(),
+            encoding: self.in_encoding,
+            parser: self.parser.clone(),
+            commenter: self.has_comment().then(|| self.commenter.clone()),
+            profile: self.profile.clone(),
+            header: self.do_names
+        }));
+    }
+
+    // Reloads file when templates have not been changed for a while, instead of on every key press.
+    fn load_later (&mut self) {
+        self.load_at = Some(Instant::now() + LOAD_DELAY);
+    }
+
+    fn receive_rows (&mut self, context: &egui::Context) {
+        if let Some(time) = self.load_at {
+            let now = Instant::now();
+            if now < time {
+                context.request_repaint_after(time - now);
+                return;
+            }
+            self.load_file();
+        }
+        let Some(loader) = &self.loader else { return };
+        let mut finished = loader.is_finished(); // Checked before messages are read, so that none are missed.
+        for message in loader.messages() {
+            if self.stale {
+                self.data = Arc::new(Table::new());
+                self.notes = Table::new();
+                self.partial = 0;
+                self.proposal = None;
+                self.stale = false;
+            }
+            match message {
+                Loaded::Sniffed(dialect, header) => {
+                    self.dialect = dialect;
+                    let _ = self.parser.set_dialect(dialect.unwrap_or_default()); // Errors come from source template, and are shown already.
+                    self.header = header;
+                },
+                Loaded::Rows(rows) => {
+                    for row in rows {
+                        if row.comment {
+                            self.notes.add(row.number, &row.text, row.slices);
+                        } else {
+                            self.partial += usize::from(row.partial);
+                            Arc::make_mut(&mut self.data).add(row.number, &row.text, row.slices);
                         }
                     }
+                },
+                Loaded::Finished(encoding, ending, samples) => {
+                    self.encoding = encoding;
+                    self.ending = ending;
+                    // Offer a template from header names, or one guessed when the current one does not match most of the rows.
+                    self.proposal = if self.header.is_some() {
+                        self.header.clone()
+                    } else if self.data.failures().len() * 2 > self.data.row_count() {
+                        inference::propose(&samples.iter().map(String::as_str).collect::<Vec<&str>>(), self.dialect.as_ref())
+                    } else {
+                        None
+                    };
+                    finished = true;
+                },
+                Loaded::Failed(error) => {
+                    self.load_error = error;
+                    finished = true;
                 }
             }
         }
-        self.state = StateTracker::Idle;
+        if finished {
+            self.loader = None;
+        } else {
+            context.request_repaint_after(Duration::from_millis(50));
+        }
+    }
+
+    // Sets templates again after matching rules change, so that errors shown are those of the templates.
+    fn set_templates (&mut self) {
+        self.source_error = self.parser.set_source(&self.source).as_message();
+        self.target_error = self.parser.set_target(&self.target).as_message();
+        self.set_comment();
+    }
+
+    // Comment template is optional, comment lines are only read when it is set and valid.
+    fn set_comment (&mut self) {
+        self.comment_error = if self.comment.trim().is_empty() { String::new() } else { self.commenter.set_source(&self.comment).as_message() };
+    }
+
+    fn has_comment (&self) -> bool {
+        !self.comment.trim().is_empty() && self.comment_error.is_empty()
+    }
+
+    // Folder that exports are written to, unless another one is chosen in save dialog.
+    fn out_folder (&self) -> Option<std::path::PathBuf> {
+        if self.out_folder.trim().is_empty() {
+            dirs::desktop_dir().filter(|d| d.is_dir()).or_else(dirs::home_dir)
+        } else {
+            Some(std::path::PathBuf::from(self.out_folder.trim()))
+        }
+    }
+
+    // File name of export, from pattern.
+    fn out_name (&self) -> String {
+        let stem = std::path::Path::new(&self.path).file_stem().unwrap_or_default().to_string_lossy();
+        placeholder::expand(&self.out_pattern, &[
+            ("stem", &stem),
+            ("template", &self.name),
+            ("date", &placeholder::today()),
+            ("time", &placeholder::now()),
+            ("ext", self.out_format.extension())
+        ])
+    }
+
+    // Asks what to do if file (or its rejects file) already exists, before writing it.
+    fn save_file (&mut self, path: std::path::PathBuf) {
+        if path.exists() || (self.do_reject && exporter::rejects_path(&path).exists()) {
+            self.conflict = Some(path);
+        } else {
+            self.write_file(path);
+        }
+    }
+
+    fn export_options (&self) -> format::Options {
+        let mut options = self.out_options.clone();
+        if options.table.trim().is_empty() {
+            options.table.clone_from(&self.name); // Template name is used if there is no table name.
+        }
+        if options.root.trim().is_empty() {
+            options.root = String::from("rows");
+        }
+        if options.row.trim().is_empty() {
+            options.row = String::from("row");
+        }
+        options.charset = self.out_encoding.name().to_string();
+        options.source = std::path::Path::new(&self.path).file_name().unwrap_or_default().to_string_lossy().to_string();
+        options.template.clone_from(&self.name);
+        options
+    }
+
+    // Starts writing file in the background, outcome is shown when done (in receive_export).
+    fn write_file (&mut self, path: std::path::PathBuf) {
+        let rejects = self.do_reject.then(|| exporter::rejects_path(&path));
+        self.notice = None;
+        self.exporter = Some(exporter::start(exporter::Request {
+            path,
+            rejects,
+            table: Arc::clone(&self.data),
+            parser: self.parser.clone(),
+            dialect: self.dialect.unwrap_or_default(), // Numbers are read as the input file is written.
+            quotes: self.do_quotes,
+            header: self.do_header,
+            encoding: self.out_encoding,
+            bom: self.do_bom,
+            ending: self.out_ending.or(self.ending),
+            terminate: self.do_terminate,
+            format: self.out_format,
+            options: self.export_options()
+        }));
+    }
+
+    fn create_conflict (&mut self, context: &egui::Context) {
+        let Some(path) = self.conflict.clone() else { return };
+        let free = exporter::free_path(&path, self.do_reject);
+        let mut choice = None;
+        let modal = egui::Modal::new(egui::Id::new("Conflict")).show(context, |ui| {
+            ui.label(egui::RichText::new("FILE EXISTS").small().weak());
+            ui.label(format!("{} already exists.", path.display()));
+            ui.add_space(8.0);
+            ui.horizontal(|ui| {
+                if ui.button("Overwrite").clicked() {
+                    choice = Some(path.clone());
+                }
+                if ui.button(format!("Save as {}", free.file_name().unwrap_or_default().to_string_lossy())).clicked() {
+                    choice = Some(free.clone());
+                }
+                if ui.button("Cancel").clicked() {
+                    self.conflict = None;
+                }
+            });
+        });
+        if modal.should_close() {
+            self.conflict = None;
+        }
+        if let Some(path) = choice {
+            self.conflict = None;
+            self.write_file(path);
+        }
+    }
+
+    fn receive_export (&mut self, context: &egui::Context) {
+        let Some(exporter) = &self.exporter else { return };
+        let finished = exporter.is_finished(); // Checked before messages are read, so that none are missed.
+        for message in exporter.messages() {
+            self.notice = match message {
+                Exported::Written(path, rows) => Some(Ok(format!("Wrote {rows} rows to {}.", path.display()))),
+                Exported::Cancelled => Some(Err(String::from("Export was cancelled, nothing was written."))),
+                Exported::Failed(error) => Some(Err(error))
+            };
+        }
+        if finished {
+            self.exporter = None;
+        } else {
+            context.request_repaint_after(Duration::from_millis(50));
+        }
     }
 
 }
@@ -372,15 +1057,17 @@ impl App for Reshaper
         });
         // Must be last for remaining size in the middle to be calculated correctly.
         egui::CentralPanel::default().frame(self.get_frame()).show_inside(ui, |ui| {
-            if self.state == StateTracker::Saving { // Needs to be in separate thread to be visible?
-                egui::CentralPanel::default().frame(self.get_frame()).show_inside(ui, |ui| {
-                    ui.vertical_centered(|ui| {
-                        ui.add_space(48.0);
-                        ui.spinner();
-                        ui.label("Processing data ...");
-                    });
+            if let Some(exporter) = &self.exporter {
+                let mut cancel = false;
+                ui.horizontal(|ui| {
+                    ui.add(egui::ProgressBar::new(exporter.progress()).desired_width(240.0).show_percentage());
+                    ui.label(egui::RichText::new("Writing file ...").weak());
+                    cancel = ui.small_button("Cancel").on_hover_text("Stop writing, the unfinished file is removed.").clicked();
                 });
-                return;
+                if cancel {
+                    exporter.cancel();
+                }
+                ui.add_space(8.0);
             }
             let mut hovered = egui::HoveredFile::default();
             let mut dropped = egui::DroppedFile::default();
@@ -399,14 +1086,48 @@ impl App for Reshaper
             }
             if dropped.path.is_some() && let Some(path) = &dropped.path {
                 self.path = path.display().to_string();
+                self.pick_row = 0;
+                self.picks.clear();
                 self.load_file();
             }
+            if let Some(loader) = &self.loader {
+                let mut cancel = false;
+                ui.horizontal(|ui| {
+                    ui.add(egui::ProgressBar::new(loader.progress()).desired_width(240.0).show_percentage());
+                    ui.label(egui::RichText::new(format!("{} rows read.", self.data.row_count())).weak());
+                    cancel = ui.small_button("Cancel").on_hover_text("Stop reading file, rows read so far are kept.").clicked();
+                });
+                if cancel {
+                    loader.cancel();
+                    self.loader = None;
+                }
+                ui.add_space(8.0);
+            }
             if self.data.is_empty() {
-                ui.add_sized(ui.available_size(), egui::Label::new(egui::RichText::new("(drop file here)").heading().italics().weak()));
+                let text = if self.load_error.is_empty() { "(drop file here)" } else { &self.load_error };
+                ui.add_sized(ui.available_size(), egui::Label::new(egui::RichText::new(text).heading().italics().weak()));
+            } else if self.picking {
+                self.create_picker(ui);
+            } else if self.notes_view && !self.notes.is_empty() {
+                self.create_notes(ui);
+            } else if self.lines_view {
+                self.create_lines(ui);
             } else {
                 self.create_table(ui);
             }
         });
+        if let Some(dialog) = &mut self.dialog && let Some(choice) = dialog.show(ui.ctx()) {
+            if let Choice::Save(path) = choice {
+                self.save_file(path);
+            }
+            self.dialog = None;
+        }
+        if self.conflict.is_some() {
+            self.create_conflict(ui.ctx());
+        }
+        // Last, so that a reload or export started in this frame is followed up.
+        self.receive_rows(ui.ctx());
+        self.receive_export(ui.ctx());
     }
 
 }
@@ -1,3 +1,12 @@
 pub mod row;
 pub mod table;
 pub mod parser;
+pub mod dialect;
+pub mod encoding;
+pub mod profile;
+pub mod inference;
+pub mod worker;
+pub mod loader;
+pub mod exporter;
+pub mod placeholder;
+pub mod format;
@@ -0,0 +1,115 @@
+/*
+Detects how a CSV-like file is delimited and quoted, by sampling some of its rows.
+
+sample rows:        "Anna";12,5;2024-01-02
+                    "Bo";3,25;2024-01-03
+dialect found:      delimiter ';', quote '"', decimal comma
+*/
+
+use regex::Regex;
+
+const DELIMITERS: [char; 4] = [',', ';', '\t', '|'];
+const QUOTES: [char; 2] = ['\'', '"']; // Last one is preferred when equally common.
+const DECIMALMATCHER: &str = r"^[-+]?\d+,\d+$";
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct Dialect
+{
+    pub delimiter: char,
+    pub quote: char,
+    pub decimal_comma: bool // Numbers are written like "12,5" (only possible when delimiter is not comma).
+}
+
+impl Default for Dialect {
+    fn default() -> Self {
+        Self {
+            delimiter: ',',
+            quote: '"',
+            decimal_comma: false
+        }
+    }
+}
+
+impl Dialect
+{
+    pub fn describe (&self) -> String {
+        let delimiter = match self.delimiter {
+            ';'  => "Semicolon",
+            '\t' => "Tab",
+            '|'  => "Pipe",
+            _    => "Comma"
+        };
+        let numbers = if self.decimal_comma { ", decimal comma" } else { "" };
+        format!("{delimiter} separated, quote {}{numbers}.", self.quote)
+    }
+
+    // Splits row on delimiter, except where delimiter is inside quotes. Quotes are kept in the fields.
+    pub fn fields<'a> (&self, row: &'a str) -> Vec<&'a str> {
+        let mut result = Vec::new();
+        let mut quoted = false;
+        let mut start = 0;
+        for (index, c) in row.char_indices() {
+            if c == self.quote {
+                quoted = !quoted;
+            } else if c == self.delimiter && !quoted {
+                result.push(&row[start..index]);
+                start = index + c.len_utf8();
+            }
+        }
+        result.push(&row[start..]);
+        result
+    }
+
+    // Returns field without surrounding quotes and white space.
+    pub fn unquote<'a> (&self, field: &'a str) -> &'a str {
+        let field = field.trim();
+        if field.len() >= 2 && field.starts_with(self.quote) && field.ends_with(self.quote) {
+            return &field[self.quote.len_utf8()..field.len()-self.quote.len_utf8()];
+        }
+        field
+    }
+}
+
+// Returns the dialect that splits the rows into the same (and largest) number of fields, or None if
+// no delimiter is found in the rows (like in files using white space or other text between values).
+// When comma splits as well as another delimiter, the other one wins if it leaves numbers like "12,5" whole.
+pub fn sniff (rows: &[&str]) -> Option<Dialect> {
+    let rows: Vec<&str> = rows.iter().copied().filter(|r| !r.trim().is_empty()).collect();
+    if rows.is_empty() {
+        return None;
+    }
+    // Quote is the one most often found first in a field.
+    let quote = QUOTES.into_iter().max_by_key(|q| {
+        rows.iter().map(|r| r.split(DELIMITERS).filter(|f| f.trim_start().starts_with(*q)).count()).sum::<usize>()
+    }).unwrap_or('"');
+    let decimal = Regex::new(DECIMALMATCHER).ok();
+    let decimals = |dialect: &Dialect| decimal.as_ref().is_some_and(|d| {
+        rows.iter().any(|r| dialect.fields(r).iter().any(|f| d.is_match(dialect.unquote(f))))
+    });
+    let mut best: Option<(usize, usize, Dialect)> = None; // Rows with most common field count, that count and dialect.
+    for delimiter in DELIMITERS {
+        let dialect = Dialect { delimiter, quote, decimal_comma: false };
+        let counts: Vec<usize> = rows.iter().map(|r| dialect.fields(r).len()).collect();
+        let Some(common) = counts.iter().copied().max_by_key(|c| counts.iter().filter(|n| *n == c).count()) else { continue };
+        let agreeing = counts.iter().filter(|n| **n == common).count();
+        if common > 1 && best.is_none_or(|(a, c, b)| (agreeing, common) > (a, c) || ((agreeing, common) == (a, c) && b.delimiter == ',' && decimals(&dialect))) {
+            best = Some((agreeing, common, dialect));
+        }
+    }
+    let (_, _, mut dialect) = best?;
+    dialect.decimal_comma = dialect.delimiter != ',' && decimals(&dialect);
+    Some(dialect)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::sniff;
+
+    #[test]
+    fn decimal_commas_do_not_make_comma_delimiter () {
+        let dialect = sniff(&["2024-10-25;12,5;7,25", "2024-10-26;11,5;6,75"]);
+        assert!(dialect.is_some_and(|d| d.delimiter == ';' && d.decimal_comma));
+        let dialect = sniff(&["2024-10-25,12,Anna", "2024-10-26,11,Bo"]);
+        assert!(dialect.is_some_and(|d| d.delimiter == ',' && !d.decimal_comma));
+    }
+}
@@ -0,0 +1,216 @@
+/*
+Reading and writing text in other encodings than UTF-8. Files are decoded to UTF-8 while read,
+so that rows with characters like "ä" in Windows-1252 are not lost. If encoding is not known,
+it is detected from byte order mark or guessed from the first part of the file. Line endings are
+detected from the first line, so that files can be written with the same ones.
+*/
+
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{
+    BufReader,
+    Chain,
+    Cursor,
+    Read
+};
+
+const SAMPLE_SIZE: usize = 65536;
+const CHUNK_SIZE: usize = 8192;
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, PartialEq, Default)]
+pub enum Encoding
+{
+    #[default] Auto,
+    Utf8,
+    Windows1252,
+    Latin1,
+    Utf16Le,
+    Utf16Be
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, PartialEq, Default)]
+pub enum LineEnding
+{
+    #[default] Lf,
+    Crlf,
+    Input // Same as in file read.
+}
+
+impl LineEnding
+{
+    pub const ALL: [LineEnding; 3] = [LineEnding::Lf, LineEnding::Crlf, LineEnding::Input];
+
+    pub const fn name (self) -> &'static str {
+        match self {
+            LineEnding::Lf    => "LF",
+            LineEnding::Crlf  => "CRLF",
+            LineEnding::Input => "Same as input"
+        }
+    }
+
+    // Line ending to write, with the one detected in input for Input.
+    pub const fn or (self, input: LineEnding) -> LineEnding {
+        match self {
+            LineEnding::Input => input,
+            ending => ending
+        }
+    }
+
+    // Line ending of a line read with its ending, LF if it has none.
+    pub fn detect (line: &str) -> LineEnding {
+        if line.ends_with("\r\n") { LineEnding::Crlf } else { LineEnding::Lf }
+    }
+}
+
+impl Encoding
+{
+    pub const INPUT:  [Encoding; 6] = [Encoding::Auto, Encoding::Utf8, Encoding::Windows1252, Encoding::Latin1, Encoding::Utf16Le, Encoding::Utf16Be];
+    pub const OUTPUT: [Encoding; 5] = [Encoding::Utf8, Encoding::Windows1252, Encoding::Latin1, Encoding::Utf16Le, Encoding::Utf16Be];
+
+    pub const fn name (self) -> &'static str {
+        match self {
+            Encoding::Auto        => "Detect",
+            Encoding::Utf8        => "UTF-8",
+            Encoding::Windows1252 => "Windows-1252",
+            Encoding::Latin1      => "ISO-8859-1",
+            Encoding::Utf16Le     => "UTF-16LE",
+            Encoding::Utf16Be     => "UTF-16BE"
+        }
+    }
+
+    // Byte order mark, or nothing for encodings that have none.
+    pub const fn bom (self) -> &'static [u8] {
+        match self {
+            Encoding::Auto | Encoding::Utf8 => b"\xEF\xBB\xBF",
+            Encoding::Utf16Le => b"\xFF\xFE",
+            Encoding::Utf16Be => b"\xFE\xFF",
+            Encoding::Windows1252 | Encoding::Latin1 => b""
+        }
+    }
+
+    // Characters that can not be encoded are written as "?".
+    pub fn encode (self, text: &str) -> Cow<'_, [u8]> {
+        match self {
+            Encoding::Auto | Encoding::Utf8 => Cow::Borrowed(text.as_bytes()),
+            Encoding::Utf16Le => Cow::Owned(text.encode_utf16().flat_map(u16::to_le_bytes).collect()),
+            Encoding::Utf16Be => Cow::Owned(text.encode_utf16().flat_map(u16::to_be_bytes).collect()),
+            Encoding::Latin1  => Cow::Owned(text.chars().map(|c| u8::try_from(c).unwrap_or(b'?')).collect()),
+            Encoding::Windows1252 => {
+                let mut encoder = encoding_rs::WINDOWS_1252.new_encoder();
+                let mut result = vec![0; text.len()]; // Never more bytes than in UTF-8.
+                let (mut read, mut written) = (0, 0);
+                loop {
+                    let (status, r, w) = encoder.encode_from_utf8_without_replacement(&text[read..], &mut result[written..], true);
+                    read += r;
+                    written += w;
+                    if let encoding_rs::EncoderResult::Unmappable(_) = status {
+                        result[written] = b'?';
+                        written += 1;
+                    } else {
+                        break;
+                    }
+                }
+                result.truncate(written);
+                Cow::Owned(result)
+            }
+        }
+    }
+
+    // Guess encoding from byte order mark, zeros in ASCII text (UTF-16) or if text is valid UTF-8.
+    fn detect (sample: &[u8]) -> Encoding {
+        if let Some((encoding, _)) = encoding_rs::Encoding::for_bom(sample) {
+            return if encoding == encoding_rs::UTF_16LE { Encoding::Utf16Le } else if encoding == encoding_rs::UTF_16BE { Encoding::Utf16Be } else { Encoding::Utf8 };
+        }
+        let even = sample.iter().step_by(2).filter(|b| **b == 0).count();
+        let odd  = sample.iter().skip(1).step_by(2).filter(|b| **b == 0).count();
+        if odd > sample.len() / 8 && odd > even {
+            return Encoding::Utf16Le;
+        }
+        if even > sample.len() / 8 {
+            return Encoding::Utf16Be;
+        }
+        match std::str::from_utf8(sample) {
+            Ok(_) => Encoding::Utf8,
+            Err(e) if e.error_len().is_none() => Encoding::Utf8, // Sample ends in the middle of a character.
+            Err(_) => Encoding::Windows1252
+        }
+    }
+
+    fn decoder (self) -> Option<encoding_rs::Decoder> {
+        match self {
+            Encoding::Auto | Encoding::Utf8 => Some(encoding_rs::UTF_8.new_decoder_with_bom_removal()),
+            Encoding::Windows1252 => Some(encoding_rs::WINDOWS_1252.new_decoder_without_bom_handling()),
+            Encoding::Utf16Le => Some(encoding_rs::UTF_16LE.new_decoder_with_bom_removal()),
+            Encoding::Utf16Be => Some(encoding_rs::UTF_16BE.new_decoder_with_bom_removal()),
+            Encoding::Latin1  => None
+        }
+    }
+}
+
+// Reader that returns the text of another reader as UTF-8. Malformed bytes are replaced with "\u{fffd}".
+pub struct Decoded<R: Read>
+{
+    inner: R,
+    decoder: Option<encoding_rs::Decoder>, // None for Latin-1, where each byte is a character.
+    output: Vec<u8>,
+    position: usize,
+    consumed: u64, // Bytes read from inner reader, to tell progress.
+    done: bool
+}
+
+impl<R: Read> Decoded<R>
+{
+    pub const fn consumed (&self) -> u64 {
+        self.consumed
+    }
+
+    fn fill (&mut self) -> std::io::Result<()> {
+        let mut chunk = [0; CHUNK_SIZE];
+        let count = self.inner.read(&mut chunk)?;
+        self.consumed += count as u64;
+        let mut text = String::new();
+        match &mut self.decoder {
+            Some(decoder) => {
+                text.reserve(decoder.max_utf8_buffer_length(count).unwrap_or(count * 3));
+                let _ = decoder.decode_to_string(&chunk[..count], &mut text, count == 0);
+            },
+            None => text.extend(chunk[..count].iter().map(|b| char::from(*b)))
+        }
+        self.output = text.into_bytes();
+        self.position = 0;
+        self.done = count == 0;
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for Decoded<R>
+{
+    fn read (&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+        while self.position >= self.output.len() && !self.done {
+            self.fill()?;
+        }
+        let count = (self.output.len() - self.position).min(buffer.len());
+        buffer[..count].copy_from_slice(&self.output[self.position..self.position + count]);
+        self.position += count;
+        Ok(count)
+    }
+}
+
+pub type Reader = BufReader<Decoded<Chain<Cursor<Vec<u8>>, File>>>;
+
+// Opens file for reading as UTF-8, and returns the encoding used (detected if Auto).
+pub fn open (path: &str, encoding: Encoding) -> std::io::Result<(Encoding, Reader)> {
+    let mut file = File::open(path)?;
+    let mut sample = Vec::with_capacity(SAMPLE_SIZE);
+    (&mut file).take(SAMPLE_SIZE as u64).read_to_end(&mut sample)?;
+    let encoding = if encoding == Encoding::Auto { Encoding::detect(&sample) } else { encoding };
+    let decoded = Decoded {
+        inner: Cursor::new(sample).chain(file),
+        decoder: encoding.decoder(),
+        output: Vec::new(),
+        position: 0,
+        consumed: 0,
+        done: false
+    };
+    Ok((encoding, BufReader::new(decoded)))
+}
@@ -0,0 +1,335 @@
+/*
+Writes the rows of a table with the target template (or in a format like JSON) in a separate thread, and rows that did not match
+the source template to a file of their own (if wanted). Files are first written with a temporary name,
+and renamed when all is written, so that a failed export does not leave a file that looks complete.
+
+table row:          2024-10-25 | M | 131 | 79 | 63
+target template:    <date>,<pulse>,<systolic>,<diastolic>
+file row:           2024-10-25,63,131,79
+*/
+
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{
+    BufWriter,
+    Write
+};
+use std::path::{
+    Path,
+    PathBuf
+};
+use std::sync::Arc;
+use rust_xlsxwriter::{
+    ExcelDateTime,
+    Format as CellFormat,
+    Workbook
+};
+use crate::models::dialect::Dialect;
+use crate::models::encoding::{
+    Encoding,
+    LineEnding
+};
+use crate::models::format;
+use crate::models::format::{
+    Columns,
+    Format,
+    Kind,
+    Options,
+    Writer
+};
+use crate::models::placeholder;
+use crate::models::parser::{
+    Origin,
+    Parser
+};
+use crate::models::table::Table;
+use crate::models::worker::{
+    Control,
+    Worker
+};
+
+pub enum Exported
+{
+    Written(PathBuf, usize), // Path of file and number of rows written.
+    Cancelled,
+    Failed(String)
+}
+
+// What to write and how.
+#[allow(clippy::struct_excessive_bools)]
+pub struct Request
+{
+    pub path: PathBuf,
+    pub rejects: Option<PathBuf>, // Where to write rows that did not match, if wanted.
+    pub table: Arc<Table>,
+    pub parser: Parser,
+    pub dialect: Dialect,         // Decimal comma of numbers read.
+    pub quotes: bool,
+    pub header: bool,
+    pub encoding: Encoding,
+    pub bom: bool,
+    pub ending: LineEnding,       // LF or CRLF, for all line breaks written.
+    pub terminate: bool,          // Last row (or epilogue, or end of other text formats) ends with a line break.
+    pub format: Format,
+    pub options: Options
+}
+
+pub fn start (request: Request) -> Worker<Exported> {
+    let total = request.table.row_count() + if request.rejects.is_some() { request.table.failures().len() } else { 0 };
+    Worker::start(total as u64, move |control| {
+        let result = finish(&request, export(&request, control));
+        control.send(match result {
+            Ok(Some(rows)) => Exported::Written(request.path, rows),
+            Ok(None) => Exported::Cancelled,
+            Err(error) => Exported::Failed(error)
+        });
+    })
+}
+
+// File for rows that did not match, next to the exported file.
+pub fn rejects_path (path: &Path) -> PathBuf {
+    path.with_extension("rejects.txt")
+}
+
+// First free path made by numbering file name, ie. "Blodtryck.out (2).csv", for when path is taken.
+pub fn free_path (path: &Path, rejects: bool) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = path.extension().map(|e| format!(".{}", e.to_string_lossy())).unwrap_or_default();
+    let taken = |path: &PathBuf| path.exists() || (rejects && rejects_path(path).exists());
+    (2..10_000).map(|number| path.with_file_name(format!("{stem} ({number}){extension}")))
+        .find(|p| !taken(p))
+        .unwrap_or_else(|| path.to_path_buf())
+}
+
+// Temporary file written to, in the same folder so that it can be renamed to path.
+fn temporary_path (path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".partial");
+    path.with_file_name(name)
+}
+
+fn create (path: &Path, request: &Request, text: bool) -> Result<BufWriter<File>, String> {
+    let mut file = File::create(temporary_path(path)).map(BufWriter::new).map_err(|e| failure(path, &e))?;
+    if request.bom && text {
+        file.write_all(request.encoding.bom()).map_err(|e| failure(path, &e))?;
+    }
+    Ok(file)
+}
+
+// Makes sure all is on disk before file is renamed.
+fn close (file: BufWriter<File>, path: &Path) -> Result<(), String> {
+    file.into_inner().map_err(|e| failure(path, e.error()))?.sync_all().map_err(|e| failure(path, &e))
+}
+
+fn failure (path: &Path, error: &std::io::Error) -> String {
+    format!("Could not write {}: {error}.", path.display())
+}
+
+// Moves temporary files in place when all went well, and removes them otherwise.
+fn finish (request: &Request, result: Result<Option<usize>, String>) -> Result<Option<usize>, String> {
+    let paths = std::iter::once(&request.path).chain(request.rejects.as_ref());
+    if let Ok(Some(_)) = result {
+        for path in paths {
+            let temporary = temporary_path(path);
+            if temporary.exists() {
+                std::fs::rename(&temporary, path).map_err(|e| failure(path, &e))?;
+            }
+        }
+        // No rows were rejected this time, so rejects from an earlier export (of file now replaced) would be misleading.
+        if let Some(rejects) = &request.rejects && request.table.failures().is_empty() && rejects.exists() {
+            std::fs::remove_file(rejects).map_err(|e| failure(rejects, &e))?;
+        }
+    } else {
+        for path in paths {
+            let _ = std::fs::remove_file(temporary_path(path));
+        }
+    }
+    result
+}
+
+// Text is made with "\n", and written with the line ending asked for.
+fn write (file: &mut BufWriter<File>, path: &Path, request: &Request, text: &str) -> Result<(), String> {
+    let text = match request.ending {
+        LineEnding::Crlf => Cow::Owned(text.replace('\n', "\r\n")),
+        _ => Cow::Borrowed(text)
+    };
+    file.write_all(&request.encoding.encode(&text)).map_err(|e| failure(path, &e))
+}
+
+// Returns number of rows written, or None if cancelled.
+fn export (request: &Request, control: &Control<Exported>) -> Result<Option<usize>, String> {
+    let table = &request.table;
+    let path = &request.path;
+    if request.format == Format::Sql && request.options.table.trim().is_empty() {
+        return Err(String::from("SQL table must have a name, set it under \"Table name\" (or name the templates)."));
+    }
+    let mut file = create(path, request, request.format != Format::Xlsx)?;
+    let written = match request.format.writer(columns(request), &request.options) {
+        _ if request.format == Format::Xlsx => write_workbook(request, control, &mut file)?,
+        Some(writer) => write_values(request, control, &mut file, writer)?,
+        None => write_text(request, control, &mut file)?
+    };
+    let Some(written) = written else { return Ok(None) };
+    close(file, path)?;
+    // Lines not matching source template are written as they were read.
+    if let Some(rejects) = &request.rejects && !table.failures().is_empty() {
+        let mut file = create(rejects, request, true)?;
+        for (count, row) in table.failures().iter().enumerate() {
+            if control.is_cancelled() {
+                return Ok(None);
+            }
+            control.advance((table.row_count() + count) as u64);
+            write(&mut file, rejects, request, &format!("{}\n", table.get_text(*row).unwrap_or_default()))?;
+        }
+        close(file, rejects)?;
+    }
+    Ok(Some(written))
+}
+
+fn columns (request: &Request) -> Columns {
+    let positions: Vec<usize> = request.parser.positions(Origin::Target).copied().collect();
+    Columns {
+        names: request.parser.variables(Origin::Target).cloned().collect(),
+        kinds: if request.format == Format::Text { Vec::new() } else { format::kinds(&request.table, &positions, request.dialect.decimal_comma) },
+        decimal_comma: request.dialect.decimal_comma
+    }
+}
+
+// Prologue or epilogue with placeholders filled in, as whole lines.
+fn surround (text: &str, request: &Request) -> String {
+    let rows = (request.table.row_count() - request.table.failures().len()).to_string();
+    let mut result = placeholder::expand(text, &[
+        ("rows", &rows),
+        ("file", &request.options.source),
+        ("date", &placeholder::today()),
+        ("time", &placeholder::now())
+    ]);
+    if !result.is_empty() && !result.ends_with('\n') {
+        result.push('\n');
+    }
+    result
+}
+
+// Rows made with target template, like CSV, with prologue and epilogue around them.
+fn write_text (request: &Request, control: &Control<Exported>, file: &mut BufWriter<File>) -> Result<Option<usize>, String> {
+    let table = &request.table;
+    let path = &request.path;
+    let quote = request.quotes.then_some('"');
+    let separator = match request.options.separator.as_str() {
+        "" => String::from("\n"),
+        separator => placeholder::unescape(separator)
+    };
+    write(file, path, request, &surround(&request.options.prologue, request))?;
+    if request.header {
+        write(file, path, request, &format!("{}\n", request.parser.header(quote)))?;
+    }
+    let mut written = 0;
+    for row in 0..table.row_count() {
+        if control.is_cancelled() {
+            return Ok(None);
+        }
+        control.advance(row as u64);
+        if table.get_error(row).is_some() {
+            continue; // Written to rejects, if wanted.
+        }
+        if let Some(parts) = table.get_parts(row) && let Ok(target) = request.parser.transform(parts, quote) {
+            if written > 0 {
+                write(file, path, request, &separator)?;
+            }
+            write(file, path, request, &target)?;
+            written += 1;
+        }
+    }
+    let mut end = if written > 0 { String::from("\n") } else { String::new() };
+    end.push_str(&surround(&request.options.epilogue, request));
+    if !request.terminate && end.ends_with('\n') {
+        end.pop();
+    }
+    write(file, path, request, &end)?;
+    Ok(Some(written))
+}
+
+// Rows as values with target variable names, like JSON.
+fn write_values (request: &Request, control: &Control<Exported>, file: &mut BufWriter<File>, mut writer: Box<dyn Writer + Send>) -> Result<Option<usize>, String> {
+    let table = &request.table;
+    let path = &request.path;
+    let positions: Vec<usize> = request.parser.positions(Origin::Target).copied().collect();
+    let mut last = writer.begin(); // Text is written one step behind, so that line break at the very end can be left out.
+    let mut written = 0;
+    for row in 0..table.row_count() {
+        if control.is_cancelled() {
+            return Ok(None);
+        }
+        control.advance(row as u64);
+        if table.get_error(row).is_some() {
+            continue;
+        }
+        let values: Vec<&str> = positions.iter().map(|p| table.get(row, *p).unwrap_or_default()).collect();
+        write(file, path, request, &last)?;
+        last = writer.row(&values);
+        written += 1;
+    }
+    last.push_str(&writer.end());
+    if !request.terminate && last.ends_with('\n') {
+        last.pop();
+    }
+    write(file, path, request, &last)?;
+    Ok(Some(written))
+}
+
+// Template name (or name of file read if templates have none), without characters not allowed in worksheet names.
+fn sheet_name (request: &Request) -> String {
+    let name = if request.options.template.trim().is_empty() {
+        Path::new(&request.options.source).file_stem().unwrap_or_default().to_string_lossy().to_string()
+    } else {
+        request.options.template.clone()
+    };
+    let name: String = name.trim().chars().map(|c| if "[]:*?/\\".contains(c) { '_' } else { c }).take(31).collect();
+    name.trim_matches('\'').to_string()
+}
+
+// Rows in a spreadsheet, with numbers and dates in cells of their own kind (so that they are not read with locale settings).
+fn write_workbook (request: &Request, control: &Control<Exported>, file: &mut BufWriter<File>) -> Result<Option<usize>, String> {
+    let table = &request.table;
+    let columns = columns(request);
+    let positions: Vec<usize> = request.parser.positions(Origin::Target).copied().collect();
+    let mut workbook = Workbook::new();
+    let bold = CellFormat::new().set_bold();
+    let date = CellFormat::new().set_num_format("yyyy-mm-dd");
+    let sheet = workbook.add_worksheet();
+    let _ = sheet.set_name(sheet_name(request)); // Default name is kept if not allowed.
+    let failure = |e: rust_xlsxwriter::XlsxError| format!("Could not write {}: {e}.", request.path.display());
+    for (column, name) in (0..).zip(&columns.names) {
+        sheet.write_string_with_format(0, column, name, &bold).map_err(failure)?;
+    }
+    let mut written: u32 = 0;
+    for row in 0..table.row_count() {
+        if control.is_cancelled() {
+            return Ok(None);
+        }
+        control.advance(row as u64);
+        if table.get_error(row).is_some() {
+            continue;
+        }
+        written += 1;
+        for ((column, position), kind) in (0..).zip(&positions).zip(&columns.kinds) {
+            let value = table.get(row, *position).unwrap_or_default();
+            match kind {
+                _ if value.trim().is_empty() => Ok(&mut *sheet),
+                Kind::Integer | Kind::Real => match format::number(value, columns.decimal_comma).parse::<f64>() {
+                    Ok(number) => sheet.write_number(written, column, number),
+                    Err(_) => sheet.write_string(written, column, value)
+                },
+                Kind::Date => match ExcelDateTime::parse_from_str(value.trim()) {
+                    Ok(day) => sheet.write_datetime_with_format(written, column, &day, &date),
+                    Err(_) => sheet.write_string(written, column, value)
+                },
+                Kind::Text => sheet.write_string(written, column, value)
+            }.map_err(failure)?;
+        }
+    }
+    sheet.set_freeze_panes(1, 0).map_err(failure)?;
+    sheet.autofit();
+    workbook.save_to_writer(file).map_err(failure)?;
+    Ok(Some(written as usize))
+}
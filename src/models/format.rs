@@ -0,0 +1,506 @@
+/*
+Formats that rows can be exported in, besides text made with the target template. Values are written
+with the target variable names as keys, and numbers are written as numbers when all values of a
+variable are numbers.
+
+target variables:   date, pulse
+table row:          2024-10-25 | 63
+JSON Lines:         {"date": "2024-10-25", "pulse": 63}
+SQL:                INSERT INTO "blodtryck" ("date", "pulse") VALUES ('2024-10-25', 63);
+Markdown:           | 2024-10-25 | 63 |
+HTML:               <tr><td>2024-10-25</td><td class="number">63</td></tr>
+XML:                <row date="2024-10-25"><pulse>63</pulse></row>
+*/
+
+use regex::Regex;
+use std::fmt::Write;
+use crate::models::table::Table;
+
+const INTEGERMATCHER: &str = r"^-?(?:0|[1-9]\d*)$";
+const REALMATCHER: &str = r"^-?(?:0|[1-9]\d*)\.\d+$";
+const DATEMATCHER: &str = r"^\d{4}-(?:0[1-9]|1[0-2])-(?:0[1-9]|[12]\d|3[01])$";
+const SQL_BATCH: usize = 500; // Rows per INSERT statement.
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, PartialEq, Default)]
+pub enum Format
+{
+    #[default] Text, // Made with target template.
+    Json,
+    JsonLines,
+    Sql,
+    Markdown,
+    Html,
+    Xml,
+    Xlsx // Written with rust_xlsxwriter, not with a Writer.
+}
+
+impl Format
+{
+    pub const ALL: [Format; 8] = [Format::Text, Format::Json, Format::JsonLines, Format::Sql, Format::Markdown, Format::Html, Format::Xml, Format::Xlsx];
+
+    pub const fn name (self) -> &'static str {
+        match self {
+            Format::Text      => "Target template",
+            Format::Json      => "JSON",
+            Format::JsonLines => "JSON Lines",
+            Format::Sql       => "SQL script",
+            Format::Markdown  => "Markdown table",
+            Format::Html      => "HTML table",
+            Format::Xml       => "XML",
+            Format::Xlsx      => "Excel workbook"
+        }
+    }
+
+    pub const fn extension (self) -> &'static str {
+        match self {
+            Format::Text      => "csv",
+            Format::Json      => "json",
+            Format::JsonLines => "jsonl",
+            Format::Sql       => "sql",
+            Format::Markdown  => "md",
+            Format::Html      => "html",
+            Format::Xml       => "xml",
+            Format::Xlsx      => "xlsx"
+        }
+    }
+
+    // Writer for text formats, or None for text made with target template (and for workbooks).
+    pub fn writer (self, columns: Columns, options: &Options) -> Option<Box<dyn Writer + Send>> {
+        match self {
+            Format::Text | Format::Xlsx => None,
+            Format::Json      => Some(Box::new(Json { columns, lines: false, count: 0 })),
+            Format::JsonLines => Some(Box::new(Json { columns, lines: true, count: 0 })),
+            Format::Sql       => Some(Box::new(Sql::new(columns, options))),
+            Format::Markdown  => Some(Box::new(Markdown { columns })),
+            Format::Html      => Some(Box::new(Html { columns, options: options.clone() })),
+            Format::Xml       => Some(Box::new(Xml { columns, options: options.clone() }))
+        }
+    }
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, PartialEq, Default)]
+pub enum SqlDialect
+{
+    #[default] Sqlite,
+    Postgres
+}
+
+impl SqlDialect
+{
+    pub const ALL: [SqlDialect; 2] = [SqlDialect::Sqlite, SqlDialect::Postgres];
+
+    pub const fn name (self) -> &'static str {
+        match self {
+            SqlDialect::Sqlite   => "SQLite",
+            SqlDialect::Postgres => "PostgreSQL"
+        }
+    }
+
+    const fn column_type (self, kind: Kind) -> &'static str {
+        match (self, kind) {
+            (SqlDialect::Sqlite, Kind::Integer)   => "INTEGER",
+            (SqlDialect::Sqlite, Kind::Real)      => "REAL",
+            (SqlDialect::Postgres, Kind::Integer) => "BIGINT",
+            (SqlDialect::Postgres, Kind::Real)    => "DOUBLE PRECISION",
+            (SqlDialect::Postgres, Kind::Date)    => "DATE",
+            (_, Kind::Text | Kind::Date)          => "TEXT"
+        }
+    }
+}
+
+// Settings for the formats that need them, saved with the templates.
+#[derive(serde::Deserialize, serde::Serialize, Clone, Default)]
+#[serde(default)]
+pub struct Options
+{
+    pub table: String,      // Name of SQL table, also used as title of HTML page.
+    pub sql: SqlDialect,
+    pub types: String,      // Declared SQL column types, ie. "date DATE, pulse SMALLINT", others are inferred.
+    pub root: String,       // Name of XML element around all rows.
+    pub row: String,        // Name of XML element for each row.
+    pub attributes: Vec<String>, // Variables written as XML attributes of the row, others are child elements.
+    pub prologue: String,   // Text written before rows made with target template, with placeholders like {rows}.
+    pub epilogue: String,   // Text written after them.
+    pub separator: String,  // Written between those rows, with "\n" for line break. Empty for line break only.
+    #[serde(skip)] pub source: String, // Name of file that rows were read from.
+    #[serde(skip)] pub template: String, // Name of templates, used as name of worksheet.
+    #[serde(skip)] pub charset: String // Name of encoding that file is written in.
+}
+
+impl Options
+{
+    // Declared type of a column, if any. Names and types can have blanks, like "Puls (bpm) DOUBLE PRECISION".
+    // A declaration belongs to the longest of the names it starts with, so "Puls (bpm) REAL" is not taken for "Puls".
+    fn declared (&self, name: &str, names: &[String]) -> Option<&str> {
+        self.types.split(',').map(str::trim).find_map(|declaration| {
+            let longest = names.iter().filter(|n| Self::typed(declaration, n).is_some()).map(String::len).max()?;
+            Self::typed(declaration, name).filter(|_| name.len() == longest)
+        })
+    }
+
+    // Type in declaration if it starts with name, ie. "pulse SMALLINT" with "pulse" => "SMALLINT".
+    fn typed<'a> (declaration: &'a str, name: &str) -> Option<&'a str> {
+        let kind = declaration.strip_prefix(name)?;
+        kind.starts_with(char::is_whitespace).then(|| kind.trim()).filter(|k| !k.is_empty())
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Kind
+{
+    Integer,
+    Real,
+    Date, // Like "2024-10-25".
+    Text
+}
+
+impl Kind
+{
+    // Kind of values in a column of a declared SQL type, found as SQLite does (ie. "SMALLINT" holds integers), or None if not known.
+    fn from_sql (declared: &str) -> Option<Kind> {
+        let declared = declared.to_uppercase();
+        let has = |names: &[&str]| names.iter().any(|n| declared.contains(n));
+        if has(&["INT", "SERIAL"]) {
+            Some(Kind::Integer)
+        } else if has(&["CHAR", "CLOB", "TEXT"]) {
+            Some(Kind::Text)
+        } else if has(&["REAL", "FLOA", "DOUB", "NUMERIC", "DECIMAL"]) {
+            Some(Kind::Real)
+        } else if declared == "DATE" {
+            Some(Kind::Date)
+        } else {
+            None
+        }
+    }
+}
+
+// Names and kinds of values written, in order.
+pub struct Columns
+{
+    pub names: Vec<String>,
+    pub kinds: Vec<Kind>,
+    pub decimal_comma: bool // Numbers are read with decimal comma, and written with point.
+}
+
+// Finds kind of each column, from the values in all rows that matched. Decimal comma is taken as point.
+pub fn kinds (table: &Table, columns: &[usize], decimal_comma: bool) -> Vec<Kind> {
+    let (Ok(integer), Ok(real), Ok(date)) = (Regex::new(INTEGERMATCHER), Regex::new(REALMATCHER), Regex::new(DATEMATCHER)) else {
+        return vec![Kind::Text; columns.len()];
+    };
+    columns.iter().map(|column| {
+        let mut result = None;
+        for index in (0..table.row_count()).filter(|i| table.get_error(*i).is_none()) {
+            let value = number(table.get(index, *column).unwrap_or_default(), decimal_comma);
+            let kind = if value.is_empty() {
+                continue; // Missing values do not tell anything.
+            } else if integer.is_match(&value) {
+                Kind::Integer
+            } else if real.is_match(&value) {
+                Kind::Real
+            } else if date.is_match(&value) {
+                Kind::Date
+            } else {
+                return Kind::Text;
+            };
+            result = match (result, kind) {
+                (None, kind) => Some(kind),
+                (Some(Kind::Date), Kind::Date) => Some(Kind::Date),
+                (Some(Kind::Date), _) | (_, Kind::Date) => return Kind::Text,
+                (Some(Kind::Real), _) | (_, Kind::Real) => Some(Kind::Real),
+                _ => Some(Kind::Integer)
+            };
+        }
+        result.unwrap_or(Kind::Text)
+    }).collect()
+}
+
+// Value as a number would be written, ie. "12,5" as "12.5" with decimal comma.
+pub fn number (value: &str, decimal_comma: bool) -> String {
+    let value = value.trim();
+    if decimal_comma { value.replacen(',', ".", 1) } else { value.to_string() }
+}
+
+// Writes the parts of a file, each returned as text.
+pub trait Writer
+{
+    fn begin (&mut self) -> String;
+    fn row (&mut self, values: &[&str]) -> String; // Values in the order of names.
+    fn end (&mut self) -> String;
+}
+
+struct Json
+{
+    columns: Columns,
+    lines: bool, // One object per line, instead of an array of objects.
+    count: usize
+}
+
+impl Json
+{
+    fn string (text: &str) -> String {
+        let mut result = String::with_capacity(text.len() + 2);
+        result.push('"');
+        for c in text.chars() {
+            match c {
+                '"'  => result.push_str("\\\""),
+                '\\' => result.push_str("\\\\"),
+                '\n' => result.push_str("\\n"),
+                '\r' => result.push_str("\\r"),
+                '\t' => result.push_str("\\t"),
+                c if c.is_control() => { let _ = write!(result, "\\u{:04x}", u32::from(c)); },
+                c => result.push(c)
+            }
+        }
+        result.push('"');
+        result
+    }
+
+    fn value (&self, value: &str, kind: Kind) -> String {
+        match kind {
+            Kind::Text | Kind::Date => Self::string(value),
+            _ if value.trim().is_empty() => String::from("null"),
+            _ => number(value, self.columns.decimal_comma)
+        }
+    }
+}
+
+impl Writer for Json
+{
+    fn begin (&mut self) -> String {
+        if self.lines { String::new() } else { String::from("[") }
+    }
+
+    fn row (&mut self, values: &[&str]) -> String {
+        let fields: Vec<String> = self.columns.names.iter().zip(&self.columns.kinds).zip(values)
+            .map(|((name, kind), value)| format!("{}: {}", Self::string(name), self.value(value, *kind)))
+            .collect();
+        let object = format!("{{{}}}", fields.join(", "));
+        self.count += 1;
+        match (self.lines, self.count) {
+            (true, _)  => format!("{object}\n"),
+            (false, 1) => format!("\n  {object}"),
+            (false, _) => format!(",\n  {object}")
+        }
+    }
+
+    fn end (&mut self) -> String {
+        if self.lines { String::new() } else { String::from("\n]\n") }
+    }
+}
+
+struct Sql
+{
+    columns: Columns,
+    options: Options,
+    count: usize
+}
+
+impl Sql
+{
+    // Values are written as declared types say, and as found from values for other columns.
+    fn new (mut columns: Columns, options: &Options) -> Self {
+        for (name, kind) in columns.names.iter().zip(columns.kinds.iter_mut()) {
+            if let Some(declared) = options.declared(name, &columns.names).and_then(Kind::from_sql) {
+                *kind = declared;
+            }
+        }
+        Self { columns, options: options.clone(), count: 0 }
+    }
+
+    // Identifiers are quoted the same way in SQLite and PostgreSQL.
+    fn identifier (name: &str) -> String {
+        format!("\"{}\"", name.replace('"', "\"\""))
+    }
+
+    fn text (value: &str) -> String {
+        format!("'{}'", value.replace('\'', "''"))
+    }
+
+    // Empty values are NULL, except in text columns.
+    fn value (&self, value: &str, kind: Kind) -> String {
+        match kind {
+            Kind::Text => Self::text(value),
+            _ if value.trim().is_empty() => String::from("NULL"),
+            Kind::Date => Self::text(value.trim()),
+            _ => {
+                let number = number(value, self.columns.decimal_comma);
+                // Values that are not numbers (in a column declared as numbers) are left for the database to reject.
+                if number.parse::<f64>().is_ok_and(f64::is_finite) { number } else { Self::text(value) }
+            }
+        }
+    }
+}
+
+impl Writer for Sql
+{
+    fn begin (&mut self) -> String {
+        let dialect = self.options.sql;
+        let columns: Vec<String> = self.columns.names.iter().zip(&self.columns.kinds)
+            .map(|(name, kind)| format!("  {} {}", Self::identifier(name), self.options.declared(name, &self.columns.names).unwrap_or(dialect.column_type(*kind))))
+            .collect();
+        format!("BEGIN;\nCREATE TABLE IF NOT EXISTS {} (\n{}\n);\n", Self::identifier(&self.options.table), columns.join(",\n"))
+    }
+
+    fn row (&mut self, values: &[&str]) -> String {
+        let values: Vec<String> = self.columns.kinds.iter().zip(values).map(|(kind, value)| self.value(value, *kind)).collect();
+        let tuple = format!("  ({})", values.join(", "));
+        self.count += 1;
+        if self.count % SQL_BATCH != 1 {
+            return format!(",\n{tuple}");
+        }
+        let names: Vec<String> = self.columns.names.iter().map(|n| Self::identifier(n)).collect();
+        let end = if self.count == 1 { "" } else { ";\n" };
+        format!("{end}INSERT INTO {} ({}) VALUES\n{tuple}", Self::identifier(&self.options.table), names.join(", "))
+    }
+
+    fn end (&mut self) -> String {
+        let end = if self.count == 0 { "" } else { ";\n" };
+        format!("{end}COMMIT;\n")
+    }
+}
+
+struct Markdown
+{
+    columns: Columns
+}
+
+impl Markdown
+{
+    // Pipes would end the cell, line breaks the row, and tags and entities would be read as HTML.
+    fn escape (text: &str) -> String {
+        text.replace('\\', "\\\\").replace('|', "\\|").replace('&', "&amp;").replace('<', "&lt;").replace(['\r', '\n'], " ")
+    }
+}
+
+impl Writer for Markdown
+{
+    fn begin (&mut self) -> String {
+        let names: Vec<String> = self.columns.names.iter().map(|n| Self::escape(n)).collect();
+        let aligns: Vec<&str> = self.columns.kinds.iter().map(|k| if matches!(k, Kind::Integer | Kind::Real) { "--:" } else { "---" }).collect();
+        format!("| {} |\n| {} |\n", names.join(" | "), aligns.join(" | "))
+    }
+
+    fn row (&mut self, values: &[&str]) -> String {
+        let values: Vec<String> = values.iter().map(|v| Self::escape(v)).collect();
+        format!("| {} |\n", values.join(" | "))
+    }
+
+    fn end (&mut self) -> String {
+        String::new()
+    }
+}
+
+struct Html
+{
+    columns: Columns,
+    options: Options
+}
+
+impl Html
+{
+    fn escape (text: &str) -> String {
+        text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+    }
+}
+
+impl Writer for Html
+{
+    fn begin (&mut self) -> String {
+        let names: Vec<String> = self.columns.names.iter().map(|n| format!("<th>{}</th>", Self::escape(n))).collect();
+        // Browsers do not read UTF-16 names from meta, but find it from byte order mark.
+        let charset = if self.options.charset.starts_with("UTF-16") { String::new() } else { format!("<meta charset=\"{}\">\n", Self::escape(&self.options.charset)) };
+        format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n{}<title>{}</title>\n\
+            <style>table {{ border-collapse: collapse; }} th, td {{ border: 1px solid #ccc; padding: 2px 8px; }} .number {{ text-align: right; }}</style>\n\
+            </head>\n<body>\n<table>\n<thead>\n<tr>{}</tr>\n</thead>\n<tbody>\n",
+            charset, Self::escape(&self.options.table), names.concat()
+        )
+    }
+
+    fn row (&mut self, values: &[&str]) -> String {
+        let cells: Vec<String> = self.columns.kinds.iter().zip(values).map(|(kind, value)| match kind {
+            Kind::Text | Kind::Date => format!("<td>{}</td>", Self::escape(value)),
+            _ => format!("<td class=\"number\">{}</td>", Self::escape(value))
+        }).collect();
+        format!("<tr>{}</tr>\n", cells.concat())
+    }
+
+    fn end (&mut self) -> String {
+        String::from("</tbody>\n</table>\n</body>\n</html>\n")
+    }
+}
+
+struct Xml
+{
+    columns: Columns,
+    options: Options
+}
+
+impl Xml
+{
+    // Characters not allowed in XML 1.0 are left out.
+    fn escape (text: &str, attribute: bool) -> String {
+        let mut result = String::with_capacity(text.len());
+        for c in text.chars() {
+            match c {
+                '&' => result.push_str("&amp;"),
+                '<' => result.push_str("&lt;"),
+                '>' => result.push_str("&gt;"),
+                '"' if attribute => result.push_str("&quot;"),
+                '\n' if attribute => result.push_str("&#10;"),
+                '\r' => result.push_str("&#13;"),
+                '\t' if attribute => result.push_str("&#9;"),
+                '\n' | '\t' => result.push(c),
+                c if c.is_control() || matches!(c, '\u{fffe}' | '\u{ffff}') => (),
+                c => result.push(c)
+            }
+        }
+        result
+    }
+
+    // Variable names may have characters not allowed in element names, ie. "Puls (bpm)" is written as "Puls__bpm_".
+    fn name (text: &str) -> String {
+        let mut result: String = text.trim().chars().map(|c| if c.is_alphanumeric() || matches!(c, '_' | '-' | '.') { c } else { '_' }).collect();
+        if !result.starts_with(|c: char| c.is_alphabetic() || c == '_') || result.to_lowercase().starts_with("xml") {
+            result.insert(0, '_');
+        }
+        result
+    }
+
+    fn value (&self, value: &str, kind: Kind) -> String {
+        match kind {
+            Kind::Integer | Kind::Real => number(value, self.columns.decimal_comma),
+            Kind::Text | Kind::Date => value.to_string()
+        }
+    }
+}
+
+impl Writer for Xml
+{
+    fn begin (&mut self) -> String {
+        format!("<?xml version=\"1.0\" encoding=\"{}\"?>\n<{}>\n", Self::escape(&self.options.charset, true), Self::name(&self.options.root))
+    }
+
+    fn row (&mut self, values: &[&str]) -> String {
+        let mut attributes = String::new();
+        let mut children = String::new();
+        for ((name, kind), value) in self.columns.names.iter().zip(&self.columns.kinds).zip(values) {
+            let value = self.value(value, *kind);
+            if self.options.attributes.contains(name) {
+                let _ = write!(attributes, " {}=\"{}\"", Self::name(name), Self::escape(&value, true));
+            } else {
+                let _ = writeln!(children, "    <{0}>{1}</{0}>", Self::name(name), Self::escape(&value, false));
+            }
+        }
+        let row = Self::name(&self.options.row);
+        if children.is_empty() {
+            format!("  <{row}{attributes}/>\n")
+        } else {
+            format!("  <{row}{attributes}>\n{children}  </{row}>\n")
+        }
+    }
+
+    fn end (&mut self) -> String {
+        format!("</{}>\n", Self::name(&self.options.root))
+    }
+}
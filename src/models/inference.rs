@@ -0,0 +1,113 @@
+/*
+Proposes a source template from a few sample rows, by finding values (dates, times, numbers, 
+words and quoted text) and treating everything between them as literal delimiters.
+
+sample rows:        2024-10-25 M: 131/79 63
+                    2024-10-25 K: 133/81 82
+proposed template:  <date> <text>: <number>/<number 2> <number 3>
+
+If a dialect is known (ie. the file is CSV-like), each field is instead one value.
+
+sample rows:        "Anna";12,5;2024-01-02
+proposed template:  <field>;<number>;<date>
+*/
+
+use regex::Regex;
+use std::collections::HashMap;
+use std::fmt::Write;
+use crate::models::parser::Parser;
+use crate::models::dialect::Dialect;
+
+pub const SAMPLE_ROWS: usize = 20;
+
+const VALUEMATCHER: &str = r#"(?x)
+    (?P<quoted>"[^"]*")
+  | (?P<date>\d{4}-\d{2}-\d{2}|\d{1,2}[./]\d{1,2}[./]\d{4})
+  | (?P<time>\d{1,2}:\d{2}(?::\d{2})?)
+  | (?P<number>[-+]?\d+(?:\.\d+)?)
+  | (?P<text>\p{L}[\p{L}\p{N}_]*)
+"#;
+const KINDS: [&str; 5] = ["quoted", "date", "time", "number", "text"];
+
+#[derive(PartialEq)]
+enum Token {
+    Value(&'static str), // Kind of value, also used as base for the variable name.
+    Literal(String)
+}
+
+fn tokenize (matcher: &Regex, row: &str) -> Vec<Token> {
+    let mut result = Vec::new();
+    let mut last = 0;
+    for capture in matcher.captures_iter(row) {
+        let Some(whole) = capture.get(0) else { continue };
+        if whole.start() > last {
+            result.push(Token::Literal(row[last..whole.start()].to_string()));
+        }
+        if let Some(kind) = KINDS.iter().find(|k| capture.name(k).is_some()) {
+            result.push(Token::Value(kind));
+        }
+        last = whole.end();
+    }
+    if last < row.len() {
+        result.push(Token::Literal(row[last..].to_string()));
+    }
+    result
+}
+
+// Every field is one value, of the kind found if the field holds nothing else.
+fn tokenize_fields (matcher: &Regex, dialect: &Dialect, row: &str) -> Vec<Token> {
+    let mut result = Vec::new();
+    for field in dialect.fields(row) {
+        if !result.is_empty() {
+            result.push(Token::Literal(dialect.delimiter.to_string()));
+        }
+        let kind = match tokenize(matcher, dialect.unquote(field)).as_slice() {
+            _ if field.trim_start().starts_with(dialect.quote) => "quoted",
+            [Token::Value(kind)] => kind,
+            [Token::Value("number"), Token::Literal(comma), Token::Value("number")] if dialect.decimal_comma && comma == "," => "number",
+            _ => "text"
+        };
+        result.push(Token::Value(kind));
+    }
+    result
+}
+
+// Returns a template for the layout shared by most of the rows, or None if there is nothing to propose.
+pub fn propose (rows: &[&str], dialect: Option<&Dialect>) -> Option<String> {
+    let matcher = Regex::new(VALUEMATCHER).ok()?;
+    let mut layouts: Vec<(Vec<Token>, usize)> = Vec::new(); // In order of appearance, so first layout wins a tie.
+    for row in rows.iter().filter(|r| !r.trim().is_empty()).take(SAMPLE_ROWS) {
+        let layout = match dialect {
+            Some(dialect) => tokenize_fields(&matcher, dialect, row),
+            None => tokenize(&matcher, row)
+        };
+        match layouts.iter_mut().find(|(l, _)| *l == layout) {
+            Some((_, count)) => *count += 1,
+            None => layouts.push((layout, 1))
+        }
+    }
+    let (layout, _) = layouts.into_iter().rev().max_by_key(|(_, count)| *count)?;
+    let mut result = String::new();
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for token in &layout {
+        match token {
+            Token::Literal(text) => result.push_str(&regex::escape(text)), // Read as regular expression in template.
+            Token::Value(kind) => {
+                let count = counts.entry(kind).or_default();
+                *count += 1;
+                let name = if *kind == "quoted" { "field" } else { kind };
+                if *count == 1 {
+                    let _ = write!(result, "<{name}>");
+                } else {
+                    let _ = write!(result, "<{name} {count}>");
+                }
+            }
+        }
+    }
+    // Only propose what actually can be used as a template.
+    let mut parser = Parser::new();
+    if parser.set_dialect(dialect.copied().unwrap_or_default()).is_err() || parser.set_source(&result).is_err() {
+        return None;
+    }
+    Some(result)
+}
@@ -0,0 +1,138 @@
+/*
+Reads and splits the rows of a file in a separate thread. Delimiter and quote are first found from
+the first rows, and then rows are sent back in batches, so that the table can be filled (and shown)
+while the rest of the file is read.
+
+file:               2024-10-25 M: 131/79 63        -> Row { number: 1, comment: false, ... }
+                    # Snitt morgon = 121/76 71     -> Row { number: 2, comment: true, ... }
+*/
+
+use std::io::BufRead;
+use crate::models::dialect;
+use crate::models::dialect::Dialect;
+use crate::models::encoding;
+use crate::models::encoding::{
+    Encoding,
+    LineEnding
+};
+use crate::models::inference;
+use crate::models::parser::Parser;
+use crate::models::profile::Profile;
+use crate::models::worker::{
+    Control,
+    Worker
+};
+
+const BATCH_SIZE: usize = 1000;
+
+pub struct Row
+{
+    pub number: usize, // Line number in file, from 1.
+    pub text: String,  // Normalized if parser says so.
+    pub slices: Result<Vec<(usize,usize)>, &'static str>,
+    pub partial: bool, // Row matches source template only partially.
+    pub comment: bool  // Comment that matches comment template.
+}
+
+pub enum Loaded
+{
+    Sniffed(Option<Dialect>, Option<String>), // Dialect found in first rows, and source template made from header row (if asked for).
+    Rows(Vec<Row>),
+    Finished(Encoding, LineEnding, Vec<String>), // Encoding and line ending of file, and sample rows for proposing a template.
+    Failed(String)
+}
+
+// What to read and how.
+pub struct Request
+{
+    pub path: String,
+    pub encoding: Encoding,
+    pub parser: Parser,
+    pub commenter: Option<Parser>, // Comment lines are skipped if None.
+    pub profile: Profile,
+    pub header: bool               // First row (that is not skipped) is header and not data.
+}
+
+pub fn start (request: Request) -> Worker<Loaded> {
+    let size = std::fs::metadata(&request.path).map(|m| m.len()).unwrap_or_default();
+    Worker::start(size, move |control| load(&request, control))
+}
+
+fn load (request: &Request, control: &Control<Loaded>) {
+    let (encoding, mut reader) = match encoding::open(&request.path, request.encoding) {
+        Ok(opened) => opened,
+        Err(error) => {
+            control.send(Loaded::Failed(format!("Could not read file: {error}.")));
+            return;
+        }
+    };
+    // Lines are read one by one (instead of with lines()) to know how much of the file is read.
+    let mut ending = None;
+    let mut failure = None; // Error that stopped reading, rows before it are kept.
+    let lines = std::iter::from_fn(|| {
+        let mut line = String::new();
+        match reader.read_line(&mut line) {
+            Ok(0) => None,
+            Err(error) => {
+                failure = Some(error);
+                None
+            },
+            Ok(_) => {
+                control.advance(reader.get_ref().consumed());
+                ending.get_or_insert_with(|| LineEnding::detect(&line));
+                let length = line.trim_end_matches(['\r', '\n']).len();
+                line.truncate(length);
+                Some(line)
+            }
+        }
+    });
+    let mut rows = request.profile.filter(lines).with_comments(request.commenter.is_some());
+    // First rows are read ahead, so that delimiter and quote are known before rows are split.
+    let mut ahead = Vec::new();
+    let mut first = Vec::new(); // Rows that are not comments, including header.
+    while first.len() < inference::SAMPLE_ROWS && let Some((number, text)) = rows.next() {
+        if !request.profile.is_comment(&text) {
+            first.push(text.clone());
+        }
+        ahead.push((number, text));
+    }
+    let dialect = dialect::sniff(&first.iter().map(String::as_str).collect::<Vec<&str>>());
+    let mut parser = request.parser.clone();
+    let _ = parser.set_dialect(dialect.unwrap_or_default()); // Errors come from source template, and are shown already.
+    let names = first.first().filter(|_| request.header).map(|row| Parser::from_header(row, &dialect.unwrap_or_default()));
+    if !control.send(Loaded::Sniffed(dialect, names)) {
+        return;
+    }
+    let mut header = request.header;
+    let mut samples = Vec::new();
+    let mut batch = Vec::with_capacity(BATCH_SIZE);
+    for (number, text) in ahead.into_iter().chain(rows) {
+        if control.is_cancelled() {
+            return;
+        }
+        let text = parser.normalize(&text).into_owned();
+        if request.profile.is_comment(&text) {
+            // Comments not matching comment template are skipped.
+            if let Some(commenter) = &request.commenter && let Ok(slices) = commenter.split(&text) {
+                batch.push(Row { number, text, slices: Ok(slices), partial: false, comment: true });
+            }
+        } else if header {
+            header = false;
+        } else {
+            if samples.len() < inference::SAMPLE_ROWS {
+                samples.push(text.clone());
+            }
+            let partial = parser.is_partial(&text);
+            let slices = parser.split(&text);
+            batch.push(Row { number, text, slices, partial, comment: false });
+        }
+        if batch.len() >= BATCH_SIZE && !control.send(Loaded::Rows(std::mem::replace(&mut batch, Vec::with_capacity(BATCH_SIZE)))) {
+            return;
+        }
+    }
+    control.send(Loaded::Rows(batch));
+    match failure {
+        Some(error) => control.send(Loaded::Failed(format!("Could not read file: {error}."))),
+        None => control.send(Loaded::Finished(encoding, ending.unwrap_or_default(), samples))
+    };
+}
@@ -4,13 +4,28 @@ source input:       2024-10-25 M: 131/79 63
 
 target template:    <date>,<pulse>,<systolic>,<diastolic>
 target produced:    2024-10-25,63,131,79
+
+target template:    <date as "Datum">,<pulse as "Puls (bpm)">
+target headers:     Datum,Puls (bpm)
 */
 
-use regex::Regex;
+use regex::{
+    Regex,
+    RegexBuilder
+};
+use unicode_normalization::{
+    UnicodeNormalization,
+    IsNormalized,
+    is_nfc_quick
+};
+use std::borrow::Cow;
 use std::slice::Iter;
 use std::fmt::Write;
+use crate::models::dialect::Dialect;
 
 const TAGMATCHER: &str = r"<([^>]*)>|([^<>]*)";
+const ENCLOSERS: &str = "\"'()[]{}"; // Quotes and brackets around values in target template.
+const ALIASMATCHER: &str = r#"^(.+?)\s+as\s+"([^"]*)"$"#; // Target variable with header name, ie. <pulse as "Puls (bpm)">.
 
 #[derive(Clone, Copy)]
 pub enum Origin {
@@ -18,18 +33,42 @@ pub enum Origin {
     Target
 }
 
-#[derive(Default)]
+// Tells why a row does not match the source template.
+pub struct Mismatch {
+    pub matched: String, // Longest part of source template that matched, ie. "<date> <time>: <systolic>".
+    pub column: usize,   // Position in row (in characters, from 1) where matching broke down.
+    pub reason: String   // Explanation, ie. "Expected '/' after <systolic> at column 18."
+}
+
+// Parts of source template, in order.
+#[derive(Clone)]
+enum Piece {
+    Variable,       // A value to capture.
+    Literal(String) // Text between values.
+}
+
+// Variable names are only used for display, in regex the groups are named by position (ie. "v1", "v2" and so on),
+// so that any text (like "blodtryck övre" or "1st") can be used as a name.
+#[derive(Default, Clone)]
 struct Descriptor {
     variables: Vec<String>, // List of parsed variable names, ie. ["date", "pulse", "systolic", "diastolic"].
     positions: Vec<usize>   // List of positions into variables list above, ie. [0,3,2,1].
 }
 
+#[derive(Clone)]
 pub struct Parser
 {
     source: Descriptor, // Source variable names and positions in source variables list.
     target: Descriptor, // Ditto for target.
-    extractor: Regex,
-    replacer: String
+    pieces: Vec<Piece>, // Source template split into variables and literal text.
+    dialect: Dialect,   // Delimiter and quote that ends or encloses a value.
+    extractor: Regex,   // Finds template anywhere in a row.
+    anchored: Regex,    // Same as extractor, but must match the whole row.
+    strict: bool,       // Use anchored instead of extractor when splitting.
+    nocase: bool,       // Match literal text in template case-insensitively.
+    unicode: bool,      // Normalize rows to NFC before splitting.
+    replacer: String,
+    separators: Vec<String> // Text between target variables, ie. [",", ",", ","], also used between names in header.
 }
 
 impl Default for Parser {
@@ -37,8 +76,15 @@ impl Default for Parser {
         Self { 
             source: Descriptor::default(),
             target: Descriptor::default(),
+            pieces: Vec::new(),
+            dialect: Dialect::default(),
             extractor: Result::unwrap(Regex::new("")),
-            replacer: String::new()
+            anchored: Result::unwrap(Regex::new("^$")),
+            strict: false,
+            nocase: false,
+            unicode: false,
+            replacer: String::new(),
+            separators: Vec::new()
         }
     }
 }
@@ -49,6 +95,71 @@ impl Parser
         Self::default()
     }
 
+    pub fn set_strict (&mut self, strict: bool) -> &mut Self {
+        self.strict = strict;
+        self
+    }
+
+    pub fn set_nocase (&mut self, nocase: bool) -> Result<&mut Self, &str> {
+        self.nocase = nocase;
+        self.compile()?;
+        Ok(self)
+    }
+
+    pub fn set_dialect (&mut self, dialect: Dialect) -> Result<&mut Self, &str> {
+        self.dialect = dialect;
+        self.compile()?;
+        Ok(self)
+    }
+
+    pub fn set_unicode (&mut self, unicode: bool) -> &mut Self {
+        self.unicode = unicode;
+        self
+    }
+
+    // Returns row in NFC form if normalization is turned on (ie. "a\u{30a}" becomes "\u{e5}").
+    pub fn normalize<'a> (&self, row: &'a str) -> Cow<'a, str> {
+        if !self.unicode || is_nfc_quick(row.chars()) == IsNormalized::Yes {
+            return Cow::Borrowed(row);
+        }
+        Cow::Owned(row.nfc().collect())
+    }
+
+    // Creates a source template from a delimited header row, ie. "Date;Pulse" => "<Date>;<Pulse>" (and "Date|Pulse" => "<Date>\|<Pulse>").
+    pub fn from_header (row: &str, dialect: &Dialect) -> String {
+        let row = row.trim_start_matches('\u{feff}'); // Byte order mark is not part of first name.
+        let mut names: Vec<String> = Vec::new();
+        for (index, name) in dialect.fields(row).into_iter().enumerate() {
+            let mut name: String = dialect.unquote(name).chars().filter(|c| *c != '<' && *c != '>').collect();
+            if  name.trim().is_empty() {
+                name = format!("column {}", index+1);
+            }
+            let original = name.clone();
+            let mut copy = 1;
+            while names.contains(&name) { // Names must be unique in a template.
+                copy += 1;
+                name = format!("{original} {copy}");
+            }
+            names.push(name);
+        }
+        names.iter().map(|n| format!("<{n}>")).collect::<Vec<String>>().join(&regex::escape(&dialect.delimiter.to_string()))
+    }
+
+    // Creates a source template from a sample row and named parts of it, ie. "131/79" with (0,3,"high") and (4,6,"low") => "<high>/<low>".
+    pub fn from_picks (row: &str, picks: &[(usize, usize, String)]) -> String {
+        let mut result = String::new();
+        let mut last = 0;
+        for (start, end, name) in picks {
+            // Picks that overlap or are not on character boundaries of row (ie. made in another row) are left out.
+            let (Some(before), Some(_)) = (row.get(last..*start), row.get(*start..*end)) else { continue };
+            result.push_str(&regex::escape(before)); // Text between variables is read as regular expression.
+            let _ = write!(result, "<{name}>");
+            last = *end;
+        }
+        result.push_str(&regex::escape(&row[last..]));
+        result
+    }
+
     pub fn variables (&self, origin: Origin) -> Iter<'_,String> {
         match origin {
             Origin::Source => self.source.variables.iter(),
@@ -64,6 +175,8 @@ impl Parser
     }
 
     pub fn set_source (&mut self, template: &str) -> Result<&mut Self, &str> {
+        let template = self.normalize(template); // Typed text can be decomposed too.
+        let template = template.as_ref();
         if template.is_empty() {
             return Err("Template must contain at least one variable.");
         }
@@ -71,28 +184,24 @@ impl Parser
             return Err("Brackets does not match.");
         }
         let  extractor = Result::unwrap(Regex::new(TAGMATCHER));
-        let mut result = String::new();
         self.source = Descriptor::default();
+        self.pieces = Vec::new();
         for capture in extractor.captures_iter(template) {
             if let Some(variable) = capture.get(1) {
-                if variable.is_empty() {
+                if variable.as_str().trim().is_empty() {
                     return Err("Variable name must be at least one character long")
                 }
-                // Since the template contains variables in brackets, these are reused 
-                // to form named groups in regex (eg. <date> => (?P<date>"[^"]+"|[^,]+)).
-                result.push_str("(?P<");
-                result.push_str(variable.as_str());
-                result.push_str(r#">"[^"]*"|[^,]*)"#);
+                if self.source.variables.iter().any(|v| v == variable.as_str()) {
+                    return Err("Variable names must be unique.");
+                }
                 self.source.variables.push(variable.as_str().to_string());
-            } else if let Some(delimiter) = capture.get(2) {
-                result.push_str(delimiter.as_str());
+                self.pieces.push(Piece::Variable);
+            } else if let Some(delimiter) = capture.get(2) && !delimiter.is_empty() {
+                self.pieces.push(Piece::Literal(delimiter.as_str().to_string()));
             }
         }
         self.source.positions = (0..self.source.variables.len()).collect();
-        match Regex::new(&result) {
-            Ok (r) => self.extractor = r,
-            Err(_) => return Err("Error during template transformation.")
-        }
+        self.compile()?;
         if self.source.variables.is_empty() {
             return Err("No variables found.");
         }
@@ -100,6 +209,8 @@ impl Parser
     }
 
     pub fn set_target(&mut self, template: &str) -> Result<&mut Self, &str> {
+        let template = self.normalize(template); // Names must compare equal to those in source.
+        let template = template.as_ref();
         if template.is_empty() {
             return Err("Template must contain at least one variable.");
         }
@@ -107,41 +218,113 @@ impl Parser
             return Err("Brackets does not match.");
         }
         self.target = Descriptor::default();
+        self.separators = Vec::new();
         let  extractor = Result::unwrap(Regex::new(TAGMATCHER));
+        let  aliases = Result::unwrap(Regex::new(ALIASMATCHER));
         let mut result = String::new();
+        let mut separator = String::new();
         for capture in extractor.captures_iter(template) {
             if let Some(variable) = capture.get(1) {
-                if variable.is_empty() {
+                // Header name is the alias if given, otherwise the variable name.
+                let (variable, header) = match aliases.captures(variable.as_str()) {
+                    Some(alias) => (alias.get(1).map_or("", |m| m.as_str()), alias.get(2).map(|m| m.as_str())),
+                    None => (variable.as_str(), None)
+                };
+                if variable.trim().is_empty() {
                     return Err("Variable name must be at least one character long")
                 }
-                if let Some(index) = self.source.variables.iter().position(|v| v == variable.as_str()) {
+                if let Some(index) = self.source.variables.iter().position(|v| v == variable) {
+                    if !self.target.variables.is_empty() {
+                        self.separators.push(separator.clone());
+                    }
+                    separator.clear();
                     let _ = write!(result, "${}", index+1);
-                    self.target.variables.push(self.source.variables[index].clone());
+                    self.target.variables.push(header.unwrap_or(&self.source.variables[index]).to_string());
                     self.target.positions.push(index);
                 } else {
                     return Err("Variable not found in source template.");
                 }
             } else if let Some(delimiter) = capture.get(2) {
                 result.push_str(delimiter.as_str());
+                separator.push_str(delimiter.as_str());
             }
         }
         self.replacer = result;
         Ok(self)
     }
 
-    pub fn split (&self, row: &str) -> Result<Vec<(usize,usize)>, &str> {
+    // Regex for each piece of source template, with variables matching as much as possible. If stopping, a variable
+    // does not run past the first character of the literal after it (or of the literal before it, for the last variable).
+    fn fragments (&self, stopping: bool) -> Vec<String> {
+        let quote = regex::escape(&self.dialect.quote.to_string());
+        let delimiter = regex::escape(&self.dialect.delimiter.to_string());
+        let mut count = 0;
+        self.pieces.iter().enumerate().map(|(index, piece)| match piece {
+            Piece::Variable => {
+                // Variables in brackets form named groups in regex, with the name 
+                // replaced by position (eg. first <date> => (?P<v1>"[^"]*"|[^,]*)).
+                count += 1;
+                let next = self.pieces.get(index+1).or_else(|| index.checked_sub(1).and_then(|i| self.pieces.get(i)));
+                let stop = match next {
+                    Some(Piece::Literal(text)) if stopping => Self::first(text).unwrap_or_default(),
+                    _ => String::new()
+                };
+                format!("(?P<v{count}>{quote}[^{quote}]*{quote}|[^{delimiter}{stop}]*)")
+            },
+            Piece::Literal(text) => text.clone() // Text between variables is a regular expression, like " +" or "\|".
+        }).collect()
+    }
+
+    // First character that literal text (a regular expression) matches, escaped for a character class, ie. "\\|" => "\\|" and ": " => ":".
+    // None if it can match different characters or none, like "." or "[;,]" or "x?".
+    fn first (text: &str) -> Option<String> {
+        let mut chars = text.chars();
+        let class = match chars.next()? {
+            '\\' => match chars.next()? {
+                's' => String::from(r"\s"),
+                c if c.is_ascii_punctuation() => regex::escape(&c.to_string()),
+                _ => return None
+            },
+            c if ".^$*+?()[]{}|".contains(c) => return None,
+            c => regex::escape(&c.to_string())
+        };
+        if chars.as_str().starts_with(['?', '*', '{']) { // Quantifier can make the character optional.
+            return None;
+        }
+        Some(class)
+    }
+
+    fn compile (&mut self) -> Result<(), &'static str> {
+        let pattern = self.fragments(false).concat();
+        let anchored = format!("^(?:{})$", self.fragments(true).concat()); // Values stop at the text after them, so text around them is not taken in.
+        let build = |pattern: &str| RegexBuilder::new(pattern).case_insensitive(self.nocase).build();
+        match (build(&pattern), build(&anchored)) {
+            (Ok(e), Ok(a)) => {
+                self.extractor = e;
+                self.anchored  = a;
+                Ok(())
+            },
+            _ => Err("Error during template transformation.")
+        }
+    }
+
+    pub fn split (&self, row: &str) -> Result<Vec<(usize,usize)>, &'static str> {
         if row.is_empty() || self.source.variables.is_empty() {
             return Err("Nothing to split.");
         }
         let mut result: Vec<(usize,usize)> = Vec::new();
-        let mut slices = self.extractor.capture_locations();
-        let bytes = row.as_bytes();
-        self.extractor.captures_read(&mut slices, row);
+        let matcher = if self.strict { &self.anchored } else { &self.extractor };
+        let mut slices = matcher.capture_locations();
+        let quote = self.dialect.quote;
+        if matcher.captures_read(&mut slices, row).is_none() {
+            return Err(if self.is_partial(row) { "Row matches template only partially." } else { "Row does not match template." });
+        }
         for index in 1..slices.len() {
             if let Some(mut slice) = slices.get(index) {
-                if  slice.0 < bytes.len() && bytes[slice.0] == b'"' && bytes[slice.1-1] == b'"'{ 
-                    slice.0 += 1;
-                    slice.1 -= 1;
+                let value = &row[slice.0..slice.1];
+                if  value.len() >= 2 && value.starts_with(quote) && value.ends_with(quote) { 
+                    slice.0 += quote.len_utf8();
+                    slice.1 -= quote.len_utf8();
                 }
                 result.push(slice);
             }
@@ -149,7 +332,80 @@ impl Parser
         Ok(result)
     }
 
-    pub fn transform (&self, parts: &[Box<str>], do_quotes: bool) -> Result<String, &str> {
+    // Finds out how much of the source template matches row, and where it stops matching. Returns None if row matches.
+    pub fn explain (&self, row: &str) -> Option<Mismatch> {
+        if self.split(row).is_ok() || self.pieces.is_empty() {
+            return None;
+        }
+        let anchor = if self.strict { "^" } else { "" };
+        let build = |pattern: &str| RegexBuilder::new(&format!("{anchor}(?:{pattern})")).case_insensitive(self.nocase).build().ok();
+        let column = |end: usize| row[..end].chars().count() + 1;
+        let fragments = self.fragments(true); // Values do not run past the text after them, so position is where that text was expected.
+        let mut matched = String::new();
+        let mut previous = None; // Name and start of last matched variable.
+        let mut position = 0;
+        let mut count = 0;
+        for (index, piece) in self.pieces.iter().enumerate() {
+            let found = build(&fragments[..=index].concat()).and_then(|r| r.captures(row));
+            match (piece, found) {
+                (Piece::Variable, Some(found)) => {
+                    let _ = write!(matched, "<{}>", self.source.variables[count]);
+                    position = found.get(0).map_or(position, |m| m.end());
+                    previous = Some((&self.source.variables[count], found.name(&format!("v{}", count+1)).map_or(position, |m| m.start())));
+                    count += 1;
+                },
+                (Piece::Literal(text), Some(found)) => {
+                    matched.push_str(text);
+                    previous = None;
+                    position = found.get(0).map_or(position, |m| m.end());
+                },
+                (Piece::Literal(text), None) => {
+                    let reason = match previous {
+                        Some((name, start)) => {
+                            // Text is missing (or replaced, like "-" in "131-79") where value stops being letters and digits, unless value is quoted.
+                            let value = &row[start..position];
+                            if !value.starts_with(self.dialect.quote) {
+                                position = start + value.char_indices().skip(1).find(|(_, c)| !c.is_alphanumeric()).map_or(value.len(), |(i, _)| i);
+                            }
+                            format!("Expected '{text}' after <{name}> at column {}.", column(position))
+                        },
+                        None if index == 0 && self.strict => format!("Expected '{text}' at start of row."),
+                        None => format!("Expected '{text}' at column {}.", column(position))
+                    };
+                    return Some(Mismatch { matched, column: column(position), reason });
+                },
+                (Piece::Variable, None) => {
+                    return Some(Mismatch { matched, column: column(position), reason: format!("No value for <{}> at column {}.", self.source.variables[count], column(position)) });
+                }
+            }
+        }
+        // Whole template matched, so there must be text left at the end (only in strict mode).
+        Some(Mismatch { matched, column: column(position), reason: format!("Unexpected text at column {}.", column(position)) })
+    }
+
+    // True if template is found somewhere in row, but there is text before or after it.
+    pub fn is_partial (&self, row: &str) -> bool {
+        !self.anchored.is_match(row) && self.extractor.is_match(row)
+    }
+
+    // Target variable names separated as values are in target template, ie. "date,pulse" (without text before or after values).
+    // Quotes and brackets around values are left out, so "<a>";"<b>" gives a;b and not a";"b.
+    pub fn header (&self, quote: Option<char>) -> String {
+        let mut result = String::new();
+        for (index, name) in self.target.variables.iter().enumerate() {
+            if index > 0 {
+                result.extend(self.separators[index - 1].chars().filter(|c| !ENCLOSERS.contains(*c)));
+            }
+            match quote {
+                Some(quote) => { let _ = write!(result, "{quote}{name}{quote}"); },
+                None => result.push_str(name)
+            }
+        }
+        result
+    }
+
+    // Values are enclosed in quote, if given.
+    pub fn transform (&self, parts: &[Box<str>], quote: Option<char>) -> Result<String, &str> {
         if self.replacer.is_empty()  || self.target.positions.is_empty() {
             return Err("Nothing to transform.");
         }
@@ -158,8 +414,8 @@ impl Parser
         }
         let mut result = self.replacer.clone();
         for position in &self.target.positions { 
-            let part: String = if do_quotes { 
-                format!("\"{}\"", parts[*position])
+            let part: String = if let Some(quote) = quote { 
+                format!("{quote}{}{quote}", parts[*position])
             } else {
                 parts[*position].to_string()
             };
@@ -170,3 +426,29 @@ impl Parser
     }
 
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Parser;
+
+    #[test]
+    fn whole_line_rejects_text_around_values () {
+        let mut parser = Parser::new();
+        assert!(parser.set_source("<date> <time>: <systolic>/<diastolic> <pulse>").is_ok());
+        parser.set_strict(true);
+        assert_eq!(parser.split("2024-10-25 M: 131/79 63"), Ok(vec![(0,10), (11,12), (14,17), (18,20), (21,23)]));
+        assert!(parser.split("xx 2024-10-25 M: 131/79 63 junk").is_err());
+        assert!(parser.is_partial("xx 2024-10-25 M: 131/79 63 junk"));
+    }
+
+    #[test]
+    fn explain_reports_column_of_missing_text () {
+        let mut parser = Parser::new();
+        assert!(parser.set_source("<date> <time>: <systolic>/<diastolic> <pulse>").is_ok());
+        for strict in [false, true] {
+            parser.set_strict(strict);
+            let mismatch = parser.explain("2024-10-25 M: 131-79 63");
+            assert!(mismatch.is_some_and(|m| m.column == 18 && m.reason == "Expected '/' after <systolic> at column 18."));
+        }
+    }
+}
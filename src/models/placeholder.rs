@@ -0,0 +1,49 @@
+/*
+Replaces placeholders in braces with values, as in file names for export.
+
+pattern:            {stem}-{template}-{date}.csv
+values:             stem = "Blodtryck", template = "puls", date = "2024-10-27"
+result:             Blodtryck-puls-2024-10-27.csv
+*/
+
+use chrono::Local;
+
+// Unknown placeholders are left as they are, so that a misspelled one is seen in result.
+pub fn expand (pattern: &str, values: &[(&str, &str)]) -> String {
+    let mut result = pattern.to_string();
+    for (name, value) in values {
+        result = result.replace(&format!("{{{name}}}"), value);
+    }
+    result
+}
+
+// Makes "\n", "\r" and "\t" typed in a field into line breaks and tabs, and "\\" into a backslash.
+pub fn unescape (text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => result.push('\n'),
+            Some('r') => result.push('\r'),
+            Some('t') => result.push('\t'),
+            Some('\\') => result.push('\\'),
+            Some(other) => { result.push(c); result.push(other); },
+            None => result.push(c)
+        }
+    }
+    result
+}
+
+// Current date (local time) as "YYYY-MM-DD".
+pub fn today () -> String {
+    Local::now().format("%Y-%m-%d").to_string()
+}
+
+// Current time (local time) as "HHMMSS", usable in file names.
+pub fn now () -> String {
+    Local::now().format("%H%M%S").to_string()
+}
@@ -0,0 +1,109 @@
+/*
+Rules for which lines of a file that are read as rows, and which that are skipped.
+
+file:               Blodtryck export 2024      <- skip first 2 lines
+                    ========================
+                    2024-10-25 M: 131/79 63
+                    ; Snitt 128/80             <- comment (prefix ";")
+                    2024-10-26 M: 116/72 81
+                    --- end of file ---        <- skip last 1 line
+*/
+
+use std::collections::VecDeque;
+use regex::Regex;
+
+#[derive(serde::Deserialize, serde::Serialize, Clone)]
+#[serde(default)]
+pub struct Profile
+{
+    pub skip_head: usize, // Lines skipped at start of file, like a preamble.
+    pub skip_tail: usize, // Lines skipped at end of file, like a summary.
+    pub comments: String, // Prefixes of comment lines, separated by blanks, ie. "# ; //".
+    pub pattern: String,  // Lines matching this regular expression are skipped.
+    #[serde(skip)] matcher: Option<Regex>
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Self {
+            skip_head: 0,
+            skip_tail: 0,
+            comments: String::from("#"),
+            pattern: String::new(),
+            matcher: None
+        }
+    }
+}
+
+impl Profile
+{
+    // Must be called after deserialization, since the compiled pattern is not saved.
+    pub fn set_pattern (&mut self, pattern: &str) -> Result<&mut Self, &str> {
+        self.pattern = pattern.to_string();
+        self.matcher = None;
+        if !pattern.is_empty() {
+            let Ok(matcher) = Regex::new(pattern) else {
+                return Err("Skip pattern is not a valid regular expression.");
+            };
+            self.matcher = Some(matcher);
+        }
+        Ok(self)
+    }
+
+    pub fn is_comment (&self, line: &str) -> bool {
+        let trimmed = line.trim_start();
+        self.comments.split_whitespace().any(|p| trimmed.starts_with(p))
+    }
+
+    // Empty lines, comments and lines matching pattern are skipped wherever they are.
+    pub fn is_skipped (&self, line: &str, comments: bool) -> bool {
+        line.trim().is_empty()
+            || (!comments && self.is_comment(line))
+            || self.matcher.as_ref().is_some_and(|m| m.is_match(line))
+    }
+
+    // Returns the lines that are not skipped, with their line numbers (from 1).
+    pub fn filter<I: Iterator<Item = String>> (&self, lines: I) -> Filtered<'_, I> {
+        Filtered {
+            profile: self,
+            lines: lines.enumerate(),
+            held: VecDeque::new(),
+            comments: false
+        }
+    }
+}
+
+// Lines are held back until it is known that they are not among the last ones to skip.
+pub struct Filtered<'a, I: Iterator<Item = String>>
+{
+    profile: &'a Profile,
+    lines: std::iter::Enumerate<I>,
+    held: VecDeque<(usize, String)>,
+    comments: bool // Comment lines are returned too (to be read with a comment template).
+}
+
+impl<I: Iterator<Item = String>> Filtered<'_, I>
+{
+    pub const fn with_comments (mut self, comments: bool) -> Self {
+        self.comments = comments;
+        self
+    }
+}
+
+impl<I: Iterator<Item = String>> Iterator for Filtered<'_, I>
+{
+    type Item = (usize, String);
+
+    fn next (&mut self) -> Option<Self::Item> {
+        loop {
+            let (index, line) = self.lines.next()?;
+            if index < self.profile.skip_head {
+                continue;
+            }
+            self.held.push_back((index + 1, line));
+            if self.held.len() > self.profile.skip_tail && let Some((number, line)) = self.held.pop_front() && !self.profile.is_skipped(&line, self.comments) {
+                return Some((number, line));
+            }
+        }
+    }
+}
@@ -5,19 +5,25 @@
 // #![deny(clippy::panic)]
 // #![deny(unused_must_use)]
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct Row
 {
+    line: usize,           // Line number in file (from 1).
+    error: Option<String>, // Why text did not match source template, if it did not.
     text: String,
-    parts: Vec<Box<str>>
+    parts: Vec<Box<str>>,
+    slices: Vec<(usize,usize)> // Where in text the parts were found.
 }
 
 impl Row
 {
-    pub fn new (text: &str, width: usize) -> Self {
+    pub fn new (line: usize, text: &str, width: usize) -> Self {
         Self { 
+            line,
+            error: None,
             text:  text.to_string(), 
-            parts: Vec::with_capacity(width)
+            parts: Vec::with_capacity(width),
+            slices: Vec::with_capacity(width)
         }
     }
 
@@ -26,6 +32,7 @@ impl Row
             return None;
         }
         self.parts.push(self.text[start..end].into());
+        self.slices.push((start, end));
         Some(self)
     }
 
@@ -36,6 +43,27 @@ impl Row
         Some(&self.parts[column])
     }
 
+    pub fn set_error (&mut self, error: &str) -> &mut Row {
+        self.error = Some(error.to_string());
+        self
+    }
+
+    pub fn get_error (&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    pub fn get_line (&self) -> usize {
+        self.line
+    }
+
+    pub fn get_text (&self) -> &str {
+        &self.text
+    }
+
+    pub fn get_slices (&self) -> &Vec<(usize,usize)> {
+        &self.slices
+    }
+
     pub fn get_parts (&self) -> &Vec<Box<str>> {
         &self.parts
     }
@@ -1,11 +1,12 @@
 
 use crate::models::row::Row;
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct Table
 {
     width: usize,
-    rows: Vec<Row>
+    rows: Vec<Row>,
+    failures: Vec<usize> // Index of rows that did not match source template.
 }
 
 impl Table 
@@ -13,17 +14,26 @@ impl Table
     pub fn new () -> Self {
         Self { 
             width: 0,
-            rows: Vec::new()
+            rows: Vec::new(),
+            failures: Vec::new()
         }
     }
 
-    pub fn add (&mut self, text: &str, slices: Vec<(usize,usize)>) -> Option<&mut Row> {
-        if self.width == 0 {
-            self.width = slices.len();
-        }
-        let mut row = Row::new(text, self.width);
-        for range in slices {
-            row.add(range.0, range.1);
+    pub fn add (&mut self, line: usize, text: &str, slices: Result<Vec<(usize,usize)>, &str>) -> Option<&mut Row> {
+        let mut row = Row::new(line, text, self.width);
+        match slices {
+            Ok(slices) => {
+                if self.width == 0 {
+                    self.width = slices.len();
+                }
+                for range in slices {
+                    row.add(range.0, range.1);
+                }
+            },
+            Err(error) => {
+                row.set_error(error);
+                self.failures.push(self.rows.len());
+            }
         }
         self.rows.push(row);
         self.rows.last_mut()
@@ -36,6 +46,34 @@ impl Table
         self.rows[index].get(column)
     }
 
+    pub fn get_text (&self, index: usize) -> Option<&str> {
+        if index >= self.rows.len() {
+            return None;
+        }
+        Some(self.rows[index].get_text())
+    }
+
+    pub fn get_slices (&self, index: usize) -> Option<&Vec<(usize,usize)>> {
+        if index >= self.rows.len() {
+            return None;
+        }
+        Some(self.rows[index].get_slices())
+    }
+
+    pub fn get_line (&self, index: usize) -> Option<usize> {
+        if index >= self.rows.len() {
+            return None;
+        }
+        Some(self.rows[index].get_line())
+    }
+
+    pub fn get_error (&self, index: usize) -> Option<&str> {
+        if index >= self.rows.len() {
+            return None;
+        }
+        self.rows[index].get_error()
+    }
+
     pub fn get_parts (&self, index: usize) -> Option<&Vec<Box<str>>> {
         if index >= self.rows.len() {
             return None;
@@ -51,5 +89,10 @@ impl Table
         self.rows.len()
 
     }
+
+    // Indexes of rows that did not match source template.
+    pub fn failures (&self) -> &Vec<usize> {
+        &self.failures
+    }
     
 }
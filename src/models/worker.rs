@@ -0,0 +1,100 @@
+/*
+Runs a task in a separate thread, so that the window is not frozen while large files are read or
+written. The task sends its results as messages, tells how far it has come and stops when cancelled.
+
+window thread:      Worker::start(size, task) -> progress() -> messages() -> cancel()
+task thread:        task(&Control) -> send(message) -> advance(done) -> is_cancelled()
+*/
+
+use std::sync::Arc;
+use std::sync::atomic::{
+    AtomicBool,
+    AtomicU64,
+    Ordering
+};
+use std::sync::mpsc::{
+    self,
+    Receiver,
+    Sender,
+    TryIter
+};
+use std::thread::{
+    self,
+    JoinHandle
+};
+
+pub struct Worker<M>
+{
+    receiver: Receiver<M>,
+    done: Arc<AtomicU64>,     // Units of work done, like bytes read or rows written.
+    cancelled: Arc<AtomicBool>,
+    total: u64,               // Units of work in all, zero if not known.
+    handle: JoinHandle<()>
+}
+
+// Handed to the task, as its side of the worker.
+pub struct Control<M>
+{
+    sender: Sender<M>,
+    done: Arc<AtomicU64>,
+    cancelled: Arc<AtomicBool>
+}
+
+impl<M: Send + 'static> Worker<M>
+{
+    pub fn start<F> (total: u64, task: F) -> Self where F: FnOnce(&Control<M>) + Send + 'static {
+        let (sender, receiver) = mpsc::channel();
+        let done = Arc::new(AtomicU64::new(0));
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let control = Control {
+            sender,
+            done: Arc::clone(&done),
+            cancelled: Arc::clone(&cancelled)
+        };
+        Self {
+            receiver,
+            done,
+            cancelled,
+            total,
+            handle: thread::spawn(move || task(&control))
+        }
+    }
+
+    // Part of work done, from 0 to 1.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn progress (&self) -> f32 {
+        if self.total == 0 {
+            return 0.0;
+        }
+        (self.done.load(Ordering::Relaxed) as f32 / self.total as f32).min(1.0)
+    }
+
+    pub fn cancel (&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    // Messages sent by task since last call.
+    pub fn messages (&self) -> TryIter<'_, M> {
+        self.receiver.try_iter()
+    }
+
+    pub fn is_finished (&self) -> bool {
+        self.handle.is_finished()
+    }
+}
+
+impl<M> Control<M>
+{
+    // Returns false if nobody is listening anymore (worker is dropped).
+    pub fn send (&self, message: M) -> bool {
+        self.sender.send(message).is_ok()
+    }
+
+    pub fn advance (&self, done: u64) {
+        self.done.store(done, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled (&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
@@ -1,2 +1,5 @@
 pub mod errorfield;
-pub mod switch;
\ No newline at end of file
+pub mod switch;
+pub mod picker;
+pub mod highlight;
+pub mod savedialog;
\ No newline at end of file
@@ -0,0 +1,41 @@
+/*
+
+Lays out a line of text with parts of it highlighted in a different color per part, as used 
+to show which part of a line ends up in which variable.
+
+*/
+
+use eframe::egui;
+
+pub const PALETTE: [egui::Color32; 6] = [
+    egui::Color32::from_rgb(  0, 153, 127),
+    egui::Color32::from_rgb(214, 134,  36),
+    egui::Color32::from_rgb( 66, 124, 214),
+    egui::Color32::from_rgb(190,  80, 170),
+    egui::Color32::from_rgb(120, 170,  40),
+    egui::Color32::from_rgb(200,  70,  70)
+];
+
+pub fn color (index: usize) -> egui::Color32 {
+    PALETTE[index % PALETTE.len()].gamma_multiply(0.5)
+}
+
+// Spans are byte ranges into line, in order and not overlapping (others are ignored).
+pub fn layout<'a> (ui: &egui::Ui, line: &str, spans: impl Iterator<Item = &'a (usize, usize)>, text_color: egui::Color32) -> egui::text::LayoutJob {
+    let font = egui::TextStyle::Monospace.resolve(ui.style());
+    let mut job = egui::text::LayoutJob::default();
+    let mut last = 0;
+    for (index, (start, end)) in spans.enumerate() {
+        if *start < last || start > end || !line.is_char_boundary(*start) || !line.is_char_boundary(*end) {
+            continue;
+        }
+        job.append(&line[last..*start], 0.0, egui::TextFormat::simple(font.clone(), text_color));
+        job.append(&line[*start..*end], 0.0, egui::TextFormat {
+            background: color(index),
+            ..egui::TextFormat::simple(font.clone(), text_color)
+        });
+        last = *end;
+    }
+    job.append(&line[last..], 0.0, egui::TextFormat::simple(font, text_color));
+    job
+}
@@ -0,0 +1,71 @@
+/*
+
+Shows a line of text where parts can be selected with the mouse to become variables. Selected
+parts are highlighted and can be removed again by clicking on them.
+
+*/
+
+use eframe::egui;
+use eframe::egui::Widget;
+use crate::widgets::highlight;
+
+pub struct Picker<'a>
+{
+    line: &'a str,
+    picks: &'a mut Vec<(usize, usize, String)> // Byte ranges into line and variable names, sorted on start.
+}
+
+impl<'a> Picker<'a>
+{
+    pub const fn new (line: &'a str, picks: &'a mut Vec<(usize, usize, String)>) -> Self {
+        Self {
+            line,
+            picks
+        }
+    }
+
+    fn to_byte (&self, index: usize) -> usize {
+        self.line.char_indices().nth(index).map_or(self.line.len(), |(b, _)| b)
+    }
+
+    fn pick (&mut self, start: usize, end: usize) -> bool {
+        if start == end {
+            // A click on a picked part removes it.
+            let count = self.picks.len();
+            self.picks.retain(|(s, e, _)| start < *s || start >= *e);
+            return count != self.picks.len();
+        }
+        if self.picks.iter().any(|(s, e, _)| start < *e && end > *s) {
+            return false; // Parts may not overlap.
+        }
+        let mut number = self.picks.len() + 1;
+        while self.picks.iter().any(|(_, _, n)| *n == format!("value {number}")) {
+            number += 1;
+        }
+        self.picks.push((start, end, format!("value {number}")));
+        self.picks.sort_by_key(|(s, _, _)| *s);
+        true
+    }
+}
+
+impl Widget for Picker<'_>
+{
+    fn ui (mut self, ui: &mut egui::Ui) -> egui::Response {
+        let spans: Vec<(usize, usize)> = self.picks.iter().map(|(s, e, _)| (*s, *e)).collect();
+        let job = highlight::layout(ui, self.line, spans.iter(), ui.visuals().text_color());
+        let mut text = self.line;
+        let mut layouter = |ui: &egui::Ui, _: &dyn egui::TextBuffer, _: f32| ui.fonts_mut(|f| f.layout_job(job.clone()));
+        let output = egui::TextEdit::singleline(&mut text)
+            .desired_width(f32::INFINITY)
+            .layouter(&mut layouter)
+            .show(ui);
+        let mut response = output.response.response;
+        if (response.drag_stopped() || response.clicked() || response.double_clicked()) && let Some(range) = output.cursor_range {
+            let range = range.as_sorted_char_range();
+            if self.pick(self.to_byte(range.start), self.to_byte(range.end)) {
+                response.mark_changed();
+            }
+        }
+        response
+    }
+}
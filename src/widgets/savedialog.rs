@@ -0,0 +1,104 @@
+/*
+
+Dialog for choosing where to save a file, with a field for the folder path, a list of the folders
+(and files) in it to browse with, and a field for the file name.
+
+*/
+
+use std::path::{
+    Path,
+    PathBuf
+};
+use eframe::egui;
+
+pub enum Choice
+{
+    Save(PathBuf),
+    Cancel
+}
+
+pub struct SaveDialog
+{
+    pub folder: String,
+    pub name: String,
+    listed: String,             // Folder that entries are read from.
+    entries: Vec<(String, bool)> // Names in folder, and if they are folders, folders first.
+}
+
+impl SaveDialog
+{
+    pub fn new (folder: &Path, name: &str) -> Self {
+        Self {
+            folder: folder.display().to_string(),
+            name: name.to_string(),
+            listed: String::new(),
+            entries: Vec::new()
+        }
+    }
+
+    fn list (&mut self) {
+        self.listed.clone_from(&self.folder);
+        self.entries = std::fs::read_dir(&self.folder)
+            .map(|entries| entries
+                .filter_map(Result::ok)
+                .map(|e| (e.file_name().to_string_lossy().to_string(), e.path().is_dir()))
+                .filter(|(name, _)| !name.starts_with('.'))
+                .collect())
+            .unwrap_or_default();
+        self.entries.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.to_lowercase().cmp(&b.0.to_lowercase())));
+    }
+
+    pub fn path (&self) -> PathBuf {
+        Path::new(&self.folder).join(&self.name)
+    }
+
+    // Returns a choice when the dialog is closed.
+    pub fn show (&mut self, context: &egui::Context) -> Option<Choice> {
+        if self.listed != self.folder {
+            self.list();
+        }
+        let mut choice = None;
+        let modal = egui::Modal::new(egui::Id::new("Save dialog")).show(context, |ui| {
+            ui.set_width(480.0);
+            ui.label(egui::RichText::new("FOLDER").small().weak());
+            ui.horizontal(|ui| {
+                let parent = Path::new(&self.folder).parent().map(Path::to_path_buf);
+                if ui.add_enabled(parent.is_some(), egui::Button::new("\u{e5d8}")).on_hover_text("Go to parent folder.").clicked() && let Some(parent) = parent {
+                    self.folder = parent.display().to_string();
+                }
+                ui.add(egui::TextEdit::singleline(&mut self.folder).desired_width(f32::INFINITY));
+            });
+            egui::ScrollArea::vertical().max_height(240.0).auto_shrink([false, true]).show(ui, |ui| {
+                if !Path::new(&self.folder).is_dir() {
+                    ui.label(egui::RichText::new("Folder does not exist.").color(egui::Color32::RED));
+                }
+                for (name, folder) in &self.entries {
+                    if *folder {
+                        if ui.selectable_label(false, format!("\u{e2c7} {name}")).clicked() {
+                            self.folder = Path::new(&self.folder).join(name).display().to_string();
+                        }
+                    } else if ui.selectable_label(*name == self.name, egui::RichText::new(name).weak()).clicked() {
+                        self.name.clone_from(name);
+                    }
+                }
+            });
+            ui.add_space(8.0);
+            ui.label(egui::RichText::new("FILE NAME").small().weak());
+            ui.add(egui::TextEdit::singleline(&mut self.name).desired_width(f32::INFINITY));
+            ui.add_space(8.0);
+            ui.horizontal(|ui| {
+                let valid = Path::new(&self.folder).is_dir() && !self.name.trim().is_empty();
+                if ui.add_enabled(valid, egui::Button::new("\u{e161} Save")).clicked() {
+                    choice = Some(Choice::Save(self.path()));
+                }
+                if ui.button("Cancel").clicked() {
+                    choice = Some(Choice::Cancel);
+                }
+            });
+        });
+        if modal.should_close() && choice.is_none() {
+            choice = Some(Choice::Cancel);
+        }
+        choice
+    }
+}